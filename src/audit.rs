@@ -0,0 +1,300 @@
+use crate::receipt::{canonical_json, unix_millis};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// `prev_hash` of the first entry in a log
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One model lifecycle event, chained to the entry before it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, starting at 0
+    pub seq: u64,
+    pub timestamp_ms: u64,
+    /// e.g. `extraction`, `verification`, `load`, `unload`, `inference`
+    pub event: String,
+    pub model_name: String,
+    pub details: Value,
+    pub prev_hash: String,
+    /// SHA-256 of the canonical JSON of every other field
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> String {
+        let body = json!({
+            "seq": self.seq,
+            "timestamp_ms": self.timestamp_ms,
+            "event": self.event,
+            "model_name": self.model_name,
+            "details": self.details,
+            "prev_hash": self.prev_hash,
+        });
+        hex::encode(Sha256::digest(canonical_json(&body).as_bytes()))
+    }
+}
+
+/// Append-only, hash-chained JSON-lines log of model lifecycle events.
+///
+/// Each entry carries the hash of the previous one, so editing, reordering or
+/// deleting an entry breaks the chain from that point on; `verify` finds it.
+/// One process should own a log at a time: clones share the chain tail, other
+/// processes appending to the same file would fork it.
+/// The chain has no anchor of its own, so dropping the newest entries leaves a
+/// shorter log that still verifies. Keep `head` somewhere the log's writer
+/// cannot reach and check against it with `verify_head` to catch that.
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    /// `seq` and hash of the last entry written
+    tail: Arc<Mutex<(u64, String)>>,
+}
+
+impl AuditLog {
+    /// Opens (or creates) the log at `path`, verifying the existing chain first
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let tail = match std::fs::metadata(path) {
+            Ok(_) => match verify_chain(path)? {
+                Some(last) => (last.seq + 1, last.hash),
+                None => (0, GENESIS_HASH.to_string()),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (0, GENESIS_HASH.to_string()),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            tail: Arc::new(Mutex::new(tail)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Hash of the last entry written, or the genesis hash for an empty log
+    pub fn head(&self) -> String {
+        self.tail
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .1
+            .clone()
+    }
+
+    /// Appends an event and returns the entry written
+    pub fn record(&self, event: &str, model_name: &str, details: Value) -> io::Result<AuditEntry> {
+        let mut tail = self.tail.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entry = AuditEntry {
+            seq: tail.0,
+            timestamp_ms: unix_millis(),
+            event: event.to_string(),
+            model_name: model_name.to_string(),
+            details,
+            prev_hash: tail.1.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+
+        let line = serde_json::to_string(&entry)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        file.sync_all()?;
+
+        *tail = (entry.seq + 1, entry.hash.clone());
+        Ok(entry)
+    }
+
+    /// Like `record`, but a failure to write is only reported so the audited operation can go on
+    pub(crate) fn record_or_warn(&self, event: &str, model_name: &str, details: Value) {
+        if let Err(e) = self.record(event, model_name, details) {
+            eprintln!("❌ Failed to write audit entry to {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Checks every entry of the log at `path` and returns how many there are
+    pub fn verify(path: &Path) -> io::Result<u64> {
+        Ok(verify_chain(path)?.map_or(0, |last| last.seq + 1))
+    }
+
+    /// Like `verify`, but also requires the log to end at `head`, so truncation is caught
+    pub fn verify_head(path: &Path, head: &str) -> io::Result<u64> {
+        let last = verify_chain(path)?;
+        let (entries, actual) = match &last {
+            Some(last) => (last.seq + 1, last.hash.as_str()),
+            None => (0, GENESIS_HASH),
+        };
+        if actual != head {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Audit log ends at {} after {} entries, expected head {}",
+                    actual, entries, head
+                ),
+            ));
+        }
+        Ok(entries)
+    }
+}
+
+/// Walks the chain and returns its last entry, or an error naming the first broken one
+fn verify_chain(path: &Path) -> io::Result<Option<AuditEntry>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut last: Option<AuditEntry> = None;
+
+    for (index, line) in contents.lines().enumerate() {
+        let entry: AuditEntry = serde_json::from_str(line)
+            .map_err(|e| broken_chain(index, &format!("unreadable entry ({})", e)))?;
+        let expected_prev = last
+            .as_ref()
+            .map_or(GENESIS_HASH, |last| last.hash.as_str());
+
+        if entry.seq != index as u64 {
+            return Err(broken_chain(
+                index,
+                &format!("sequence number {}", entry.seq),
+            ));
+        }
+        if entry.prev_hash != expected_prev {
+            return Err(broken_chain(index, "does not link to the previous entry"));
+        }
+        if entry.hash != entry.compute_hash() {
+            return Err(broken_chain(index, "contents do not match its hash"));
+        }
+        last = Some(entry);
+    }
+    Ok(last)
+}
+
+fn broken_chain(index: usize, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Audit log broken at entry {}: {}", index, reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ClientOptions, TritonClient};
+    use crate::models::ModelExtractor;
+    use crate::test_support::{simple_inputs, simple_session, write_cassette};
+    use crate::transport::Transport;
+    use tokio_util::sync::CancellationToken;
+
+    fn events(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<AuditEntry>(line).unwrap().event)
+            .collect()
+    }
+
+    #[test]
+    fn detects_edited_and_removed_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = AuditLog::open(&path).unwrap();
+        for i in 0..3 {
+            log.record("inference", "simple", json!({ "run": i }))
+                .unwrap();
+        }
+        assert_eq!(AuditLog::verify(&path).unwrap(), 3);
+
+        // Reopening continues the same chain
+        AuditLog::open(&path)
+            .unwrap()
+            .record("unload", "simple", json!({}))
+            .unwrap();
+        assert_eq!(AuditLog::verify(&path).unwrap(), 4);
+
+        let original = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = original.lines().collect();
+
+        let edited = original.replacen("\"run\":1", "\"run\":7", 1);
+        std::fs::write(&path, edited).unwrap();
+        let err = AuditLog::verify(&path).unwrap_err();
+        assert!(err.to_string().contains("entry 1"), "{}", err);
+
+        let removed = format!("{}\n{}\n{}\n", lines[0], lines[2], lines[3]);
+        std::fs::write(&path, removed).unwrap();
+        let err = AuditLog::verify(&path).unwrap_err();
+        assert!(err.to_string().contains("entry 1"), "{}", err);
+
+        // A tampered log is not silently extended
+        assert!(AuditLog::open(&path).is_err());
+    }
+
+    #[test]
+    fn anchored_head_detects_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = AuditLog::open(&path).unwrap();
+        for i in 0..3 {
+            log.record("inference", "simple", json!({ "run": i }))
+                .unwrap();
+        }
+        let head = log.head();
+        assert_eq!(AuditLog::verify_head(&path, &head).unwrap(), 3);
+        assert_eq!(AuditLog::open(&path).unwrap().head(), head);
+
+        // Dropping the newest entry keeps the chain intact, only the head gives it away
+        let original = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = original.lines().collect();
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        assert_eq!(AuditLog::verify(&path).unwrap(), 2);
+        let err = AuditLog::verify_head(&path, &head).unwrap_err();
+        assert!(err.to_string().contains("after 2 entries"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn records_model_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = dir.path().join("simple.cassette");
+        write_cassette(&cassette, &simple_session());
+        let repo = dir.path().join("repo");
+        std::fs::create_dir_all(repo.join("simple/1")).unwrap();
+        std::fs::write(repo.join("simple/config.pbtxt"), "name: \"simple\"\n").unwrap();
+
+        let path = dir.path().join("audit.jsonl");
+        let log = AuditLog::open(&path).unwrap();
+        ModelExtractor::for_model("simple", repo.clone())
+            .with_audit_log(log.clone())
+            .ensure_extracted()
+            .unwrap();
+
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            audit_log: Some(log),
+            ..ClientOptions::default()
+        };
+        let client =
+            TritonClient::with_options("http://triton.invalid/v2", "simple", repo, options)
+                .await
+                .unwrap();
+        client
+            .run_inference(simple_inputs(), &CancellationToken::new())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            events(&path),
+            [
+                "extraction",
+                "extraction",
+                "verification",
+                "load",
+                "inference",
+                "unload"
+            ]
+        );
+        assert_eq!(AuditLog::verify(&path).unwrap(), 6);
+    }
+}
@@ -0,0 +1,234 @@
+//! Removing an extracted model once the task using it is done.
+//!
+//! `TritonClient::cleanup` unloads the model and wipes its directory: every
+//! file, version directories and manifests included, is overwritten with zeros
+//! before it is removed. `CleanupGuard` does the same for a task that ends
+//! early, by error or panic.
+
+use crate::client::TritonClient;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Files and bytes overwritten by `wipe_dir`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct WipeStats {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Outcome of `TritonClient::cleanup`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CleanupReport {
+    /// Whether the server acknowledged the unload
+    pub unloaded: bool,
+    pub files_wiped: u64,
+    pub bytes_wiped: u64,
+}
+
+/// Overwrites every file below `dir` with zeros, then removes the directory.
+///
+/// Links are removed without touching their targets. A missing directory has
+/// nothing to wipe.
+pub fn wipe_dir(dir: &Path) -> io::Result<WipeStats> {
+    let mut stats = WipeStats::default();
+    match overwrite_tree(dir, &mut stats) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !dir.exists() => return Ok(stats),
+        result => result?,
+    }
+    std::fs::remove_dir_all(dir)?;
+    Ok(stats)
+}
+
+fn overwrite_tree(dir: &Path, stats: &mut WipeStats) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            overwrite_tree(&entry.path(), stats)?;
+        } else if file_type.is_file() {
+            stats.bytes += overwrite_with_zeros(&entry.path())?;
+            stats.files += 1;
+        }
+    }
+    Ok(())
+}
+
+fn overwrite_with_zeros(path: &Path) -> io::Result<u64> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let zeros = vec![0u8; 64 * 1024];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..n])?;
+        remaining -= n as u64;
+    }
+    file.sync_all()?;
+    Ok(len)
+}
+
+/// Cleans up after a task however it ends.
+///
+/// Call `finish` at the end of the task for a full `TritonClient::cleanup`. If
+/// the guard is dropped instead, the model directory is wiped on the spot and
+/// the unload is spawned on the current Tokio runtime, when there is one.
+pub struct CleanupGuard {
+    client: Option<Arc<TritonClient>>,
+}
+
+impl CleanupGuard {
+    pub fn new(client: Arc<TritonClient>) -> Self {
+        Self {
+            client: Some(client),
+        }
+    }
+
+    /// Runs the cleanup now and disarms the guard
+    pub async fn finish(
+        mut self,
+    ) -> Result<CleanupReport, Box<dyn std::error::Error + Send + Sync>> {
+        let client = self.client.take().expect("guard is armed until finished");
+        client.cleanup().await
+    }
+}
+
+impl Deref for CleanupGuard {
+    type Target = TritonClient;
+
+    fn deref(&self) -> &TritonClient {
+        self.client
+            .as_deref()
+            .expect("guard is armed until finished")
+    }
+}
+
+impl Drop for CleanupGuard {
+    fn drop(&mut self) {
+        let Some(client) = self.client.take() else {
+            return;
+        };
+        println!("⚠️ Task ended without cleanup, wiping model");
+        if let Err(e) = client.wipe_model_dir() {
+            eprintln!("❌ Failed to wipe model directory: {}", e);
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(e) = client.unload_model().await {
+                        eprintln!("❌ Failed to unload model: {}", e);
+                    }
+                });
+            }
+            Err(_) => eprintln!("⚠️ No async runtime to unload the model from"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditEntry, AuditLog};
+    use crate::client::ClientOptions;
+    use crate::test_support::{simple_session, write_cassette};
+    use crate::transport::Transport;
+
+    /// Client for a loaded `simple` model whose cassette ends with one unload
+    async fn loaded_client(root: &Path, audit_log: &AuditLog) -> TritonClient {
+        let mut session = simple_session();
+        session.drain(3..6);
+        let cassette = root.join("simple.cassette");
+        write_cassette(&cassette, &session);
+        let repo = root.join("repo");
+        std::fs::create_dir_all(repo.join("simple/1")).unwrap();
+        std::fs::write(repo.join("simple/config.pbtxt"), "name: \"simple\"\n").unwrap();
+        std::fs::write(repo.join("simple/1/model.onnx"), b"onnx").unwrap();
+
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            audit_log: Some(audit_log.clone()),
+            ..ClientOptions::default()
+        };
+        TritonClient::with_options("http://triton.invalid/v2", "simple", repo, options)
+            .await
+            .unwrap()
+    }
+
+    fn events(audit_log: &AuditLog) -> Vec<String> {
+        std::fs::read_to_string(audit_log.path())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<AuditEntry>(line).unwrap().event)
+            .collect()
+    }
+
+    #[test]
+    fn wipes_nested_files_but_not_link_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = dir.path().join("outside.bin");
+        std::fs::write(&outside, b"keep").unwrap();
+        let model = dir.path().join("model");
+        std::fs::create_dir_all(model.join("1")).unwrap();
+        std::fs::write(model.join("config.pbtxt"), b"name").unwrap();
+        std::fs::write(model.join("1/model.onnx"), vec![1u8; 100_000]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&outside, model.join("1/link")).unwrap();
+
+        let stats = wipe_dir(&model).unwrap();
+        assert_eq!(
+            stats,
+            WipeStats {
+                files: 2,
+                bytes: 100_004
+            }
+        );
+        assert!(!model.exists());
+        assert_eq!(std::fs::read(&outside).unwrap(), b"keep");
+
+        assert_eq!(wipe_dir(&model).unwrap(), WipeStats::default());
+    }
+
+    #[tokio::test]
+    async fn cleanup_unloads_wipes_and_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_log = AuditLog::open(&dir.path().join("audit.jsonl")).unwrap();
+        let client = loaded_client(dir.path(), &audit_log).await;
+
+        let report = client.cleanup().await.unwrap();
+        assert_eq!(
+            report,
+            CleanupReport {
+                unloaded: true,
+                files_wiped: 2,
+                bytes_wiped: 19,
+            }
+        );
+        assert!(!dir.path().join("repo/simple").exists());
+        assert_eq!(events(&audit_log).last().unwrap(), "cleanup");
+    }
+
+    #[tokio::test]
+    async fn dropped_guard_still_cleans_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_log = AuditLog::open(&dir.path().join("audit.jsonl")).unwrap();
+        let client = loaded_client(dir.path(), &audit_log).await;
+
+        let guard = CleanupGuard::new(Arc::new(client));
+        let failed_task = async { Err::<(), _>(format!("{} failed", guard.model_dir().display())) };
+        assert!(failed_task.await.is_err());
+        drop(guard);
+        assert!(!dir.path().join("repo/simple").exists());
+
+        for _ in 0..100 {
+            if events(&audit_log).last().map(String::as_str) == Some("unload") {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        let events = events(&audit_log);
+        assert_eq!(events[events.len() - 2..], ["wipe", "unload"]);
+    }
+}
@@ -1,14 +1,25 @@
-use crate::models::ModelExtractor;
+use crate::audit::AuditLog;
+use crate::cleanup::{wipe_dir, CleanupReport, WipeStats};
+use crate::digest::DirectoryDigest;
+use crate::encryption::KeyProvider;
+use crate::hash::{hash_file, hash_file_async, HashAlgorithm, HashProgress};
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::models::{ExtractionStatus, ModelExtractor};
+use crate::receipt::{hash_inputs, hash_outputs, unix_millis, InferenceReceipt, ReceiptConfig};
+use crate::repository::RepositoryManager;
+use crate::signing::{verify_manifest_signature, TrustedKeys};
+use crate::transport::Transport;
 use futures::{stream::StreamExt, Future, Stream};
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_json::Value;
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, Read};
+use std::collections::{HashMap, HashSet};
+use std::io;
 use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 // const TRITON_URL: &str = "http://localhost:8000/v2";
 
@@ -17,10 +28,37 @@ use std::path::PathBuf;
 // const BASE_PATH: &str = "/home/ronnie/Model";
 
 pub struct TritonClient {
-    client: Client,
+    transport: Transport,
     url: String,
     model_name: String,
     model_path: PathBuf,
+    trusted_keys: Option<TrustedKeys>,
+    receipts: Option<ReceiptConfig>,
+    audit_log: Option<AuditLog>,
+    repository: Option<RepositoryManager>,
+    /// Digest of the loaded model directory, computed only when receipts are enabled
+    model_digest: Option<String>,
+    wipe_after_task: bool,
+    task_timeout: Option<Duration>,
+    concurrency: Option<Concurrency>,
+}
+
+/// Order in which a concurrent `run` hands responses to its closure
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResponseOrder {
+    /// Same order as the requests, so a slow request holds back the ones after it
+    #[default]
+    Ordered,
+    /// As soon as each response is ready
+    Unordered,
+}
+
+/// How many requests a `run` keeps in flight at once
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Concurrency {
+    /// No more requests are read from the stream while this many are in flight
+    pub max_in_flight: usize,
+    pub order: ResponseOrder,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -44,6 +82,61 @@ impl TensorData {
             TensorData::Str(data) => json!(data),
         }
     }
+
+    /// Triton datatype name for this tensor
+    pub fn datatype(&self) -> &'static str {
+        match self {
+            TensorData::F32(_) => "FP32",
+            TensorData::I32(_) => "INT32",
+            TensorData::I64(_) => "INT64",
+            TensorData::U8(_) => "UINT8",
+            TensorData::Bool(_) => "BOOL",
+            TensorData::Str(_) => "BYTES",
+        }
+    }
+}
+
+/// Optional settings for `TritonClient::with_options`
+#[derive(Clone, Default)]
+pub struct ClientOptions {
+    /// How requests reach the server: plain HTTP, recorded HTTP, or a replayed cassette
+    pub transport: Transport,
+    /// When set, only models whose manifest is signed by one of these keys are loaded
+    pub trusted_keys: Option<TrustedKeys>,
+    /// When set, every inference gets a signed receipt appended to the receipt log
+    pub receipts: Option<ReceiptConfig>,
+    /// When set, extraction, verification, load, unload and inference events are recorded here
+    pub audit_log: Option<AuditLog>,
+    /// Keys for encrypted model archives
+    pub key_provider: Option<Arc<dyn KeyProvider>>,
+    /// How long to wait for another process extracting the same model (10 minutes if unset)
+    pub lock_timeout: Option<Duration>,
+    /// When set, the model is marked as used on load and inference, and the repository quota is enforced
+    pub repository: Option<RepositoryManager>,
+    /// Wipe the extracted model directory once `run` has drained its request stream
+    pub wipe_after_task: bool,
+    /// Limit on a whole `run`, after which the request in flight is abandoned and the model unloaded
+    pub task_timeout: Option<Duration>,
+    /// When set, `run` answers requests concurrently and unloads the model once at the end
+    /// instead of after every request
+    pub concurrency: Option<Concurrency>,
+}
+
+impl ClientOptions {
+    /// Extractor for `model_name` under `model_path` with the audit log, keys and lock timeout applied
+    pub(crate) fn extractor(&self, model_name: &str, model_path: PathBuf) -> ModelExtractor {
+        let mut extractor = ModelExtractor::for_model(model_name, model_path);
+        if let Some(audit_log) = &self.audit_log {
+            extractor = extractor.with_audit_log(audit_log.clone());
+        }
+        if let Some(key_provider) = &self.key_provider {
+            extractor = extractor.with_key_provider(key_provider.clone());
+        }
+        if let Some(timeout) = self.lock_timeout {
+            extractor = extractor.with_lock_timeout(timeout);
+        }
+        extractor
+    }
 }
 
 impl TritonClient {
@@ -51,61 +144,111 @@ impl TritonClient {
         triton_url: &str,
         model_name: &str,
         model_path: PathBuf,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::with_options(triton_url, model_name, model_path, ClientOptions::default()).await
+    }
+
+    pub async fn with_options(
+        triton_url: &str,
+        model_name: &str,
+        model_path: PathBuf,
+        options: ClientOptions,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         // Initialize the client
-        let client = TritonClient {
-            client: Client::new(),
-            url: triton_url.to_string(),
-            model_name: model_name.to_string(),
-            model_path: model_path.clone(),
-        };
+        let mut client = Self::connect(triton_url, model_name, model_path.clone(), &options);
 
-        match ModelExtractor::new(&client.model_name, model_path.clone()) {
-            Ok(extractor) => {
-                if let Err(e) = extractor.extract_model() {
-                    println!("❌ Extraction failed: {:?}", e);
-                } else {
-                    println!("✅ Model '{}' successfully extracted!", client.model_name);
-                }
+        // Waiting on another process's extraction lock must not hold up the async runtime
+        let extractor = options.extractor(&client.model_name, model_path);
+        match tokio::task::spawn_blocking(move || extractor.ensure_extracted()).await? {
+            Ok(ExtractionStatus::AlreadyPresent) => {
+                println!("✅ Model '{}' is already extracted", client.model_name);
+            }
+            Ok(status) => {
+                println!(
+                    "✅ Model '{}' successfully extracted! ({:?})",
+                    client.model_name, status
+                );
             }
             Err(e) => {
-                println!("❌ Initialization of ModelExtractor failed: {:?}", e);
+                println!("❌ Extraction failed: {:?}", e);
             }
         }
+        client.verify_manifest()?;
+        client.load_model().await?;
+        Ok(client)
+    }
+
+    /// Creates a client without touching the model or the server.
+    ///
+    /// `with_options` is this plus extraction, `verify_manifest` and `load_model`;
+    /// callers that time or control those steps one by one start here.
+    pub fn connect(
+        triton_url: &str,
+        model_name: &str,
+        model_path: PathBuf,
+        options: &ClientOptions,
+    ) -> Self {
+        TritonClient {
+            transport: options.transport.clone(),
+            url: triton_url.to_string(),
+            model_name: model_name.to_string(),
+            model_path,
+            trusted_keys: options.trusted_keys.clone(),
+            receipts: options.receipts.clone(),
+            audit_log: options.audit_log.clone(),
+            repository: options.repository.clone(),
+            model_digest: None,
+            wipe_after_task: options.wipe_after_task,
+            task_timeout: options.task_timeout,
+            concurrency: options.concurrency,
+        }
+    }
+
+    /// Checks that the server is up and loads the model into it
+    pub async fn load_model(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.receipts.is_some() {
+            let model_dir = self.model_dir();
+            let digest =
+                tokio::task::spawn_blocking(move || DirectoryDigest::compute(&model_dir)).await??;
+            self.model_digest = Some(digest.root);
+        }
         println!("⏳ Checking if the server is live...");
 
-        let mut url = format!("{}/health/live", &client.url);
-        let mut response = client.client.get(&url).send().await?;
-        if !response.status().is_success() {
-            println!("✅ Server is not live: {}", response.status());
+        let mut response = self.transport.get(&self.url, "/health/live").await?;
+        if !response.is_success() {
+            println!("✅ Server is not live: {}", response.status);
         }
         println!("✅ Server is live!");
         println!("⏳ Checking if the server is ready...");
 
-        url = format!("{}/health/ready", &client.url);
-        response = client.client.get(&url).send().await?;
-        if !response.status().is_success() {
-            println!("✅ Server is not ready: {}", response.status());
+        response = self.transport.get(&self.url, "/health/ready").await?;
+        if !response.is_success() {
+            println!("✅ Server is not ready: {}", response.status);
         }
         println!("✅ Server is ready!");
 
-        println!("⏳ Loading model: {}", client.model_name);
+        println!("⏳ Loading model: {}", self.model_name);
 
-        url = format!(
-            "{}/repository/models/{}/load",
-            &client.url, &client.model_name
-        );
-        response = client
-            .client
-            .post(&url)
-            .json(&serde_json::json!({}))
-            .send()
+        let path = format!("/repository/models/{}/load", &self.model_name);
+        response = self
+            .transport
+            .post(&self.url, &path, &serde_json::json!({}))
             .await?;
-        if response.status().is_success() {
-            println!("✅ Successfully loaded model: {}", &client.model_name);
+        self.audit("load", json!({ "status": response.status }));
+        if !response.is_success() {
+            println!("❌ Server refused to load model: {}", &self.model_name);
+            return Err(format!(
+                "Failed to load model '{}'. HTTP Status: {:?}: {}",
+                self.model_name, response.status, response.body
+            )
+            .into());
         }
+        println!("✅ Successfully loaded model: {}", &self.model_name);
 
-        Ok(client)
+        if let Some(repository) = &self.repository {
+            self.enforce_repository_quota(repository).await;
+        }
+        Ok(())
     }
 
     // Check if the server is live
@@ -161,20 +304,39 @@ impl TritonClient {
     //     }
     // }
 
+    /// Checks the SHA-256 of `1/model.onnx`, streaming the file instead of loading it
     pub fn verify_model_blob(&self, expected_hash_hex: &str) -> io::Result<()> {
-        let extracted_path = self.model_path.join(&self.model_name);
-        let model_path = extracted_path.join("1").join("model.onnx");
+        let computed_hash_hex = hash_file(&self.model_blob_path(), HashAlgorithm::Sha256)?;
+        self.compare_blob_hash(&computed_hash_hex, expected_hash_hex)
+    }
 
-        // Read model file into bytes
-        let mut model_file = File::open(model_path)?;
-        let mut model_data = Vec::new();
-        model_file.read_to_end(&mut model_data)?;
+    /// Checks `1/model.onnx` with the chosen algorithm on the blocking thread pool.
+    ///
+    /// `progress` receives `(bytes_hashed, total_bytes)` after every chunk.
+    pub async fn verify_model_blob_async(
+        &self,
+        expected_hash_hex: &str,
+        algorithm: HashAlgorithm,
+        progress: Option<HashProgress>,
+    ) -> io::Result<()> {
+        println!("⏳ Hashing model blob with {}", algorithm.name());
+        let computed_hash_hex =
+            hash_file_async(self.model_blob_path(), algorithm, progress).await?;
+        self.compare_blob_hash(&computed_hash_hex, expected_hash_hex)
+    }
 
-        // Compute actual SHA-256 of model
-        let model_sha256 = Sha256::digest(&model_data);
-        let computed_hash_hex = hex::encode(&model_sha256);
+    fn model_blob_path(&self) -> PathBuf {
+        self.model_path
+            .join(&self.model_name)
+            .join("1")
+            .join("model.onnx")
+    }
 
-        // Compare with provided hash
+    fn compare_blob_hash(
+        &self,
+        computed_hash_hex: &str,
+        expected_hash_hex: &str,
+    ) -> io::Result<()> {
         if computed_hash_hex == expected_hash_hex.to_lowercase() {
             println!("✅ Hash verification passed");
             Ok(())
@@ -182,55 +344,277 @@ impl TritonClient {
             eprintln!("❌ Hash mismatch:");
             eprintln!("  Computed : {}", computed_hash_hex);
             eprintln!("  Expected : {}", expected_hash_hex.to_lowercase());
-            std::process::exit(1);
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Model blob hash mismatch: computed {}, expected {}",
+                    computed_hash_hex,
+                    expected_hash_hex.to_lowercase()
+                ),
+            ))
+        }
+    }
+
+    /// Checks every file in the extracted model directory against `expected`.
+    ///
+    /// Unlike `verify_model_blob`, this also covers config.pbtxt, label files,
+    /// external weight files and backend scripts, and names each file that differs.
+    pub fn verify_model_directory(&self, expected: &DirectoryDigest) -> io::Result<()> {
+        let model_dir = self.model_path.join(&self.model_name);
+        let report = expected.verify(&model_dir)?;
+        if report.is_clean() {
+            println!("✅ Model directory verified: {}", expected.root);
+            Ok(())
+        } else {
+            eprintln!(
+                "❌ Model directory {:?} does not match its digest:",
+                model_dir
+            );
+            eprintln!("  {}", report);
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Model directory {:?} does not match its digest ({})",
+                    model_dir, report
+                ),
+            ))
+        }
+    }
+
+    /// Checks the extracted model against its hash manifest before it is loaded.
+    ///
+    /// Models placed in the repository by hand may have no manifest; that is
+    /// reported but allowed unless trusted keys are configured. A manifest that
+    /// fails to parse or does not match is an error. With trusted keys, the
+    /// manifest must also carry a valid signature from one of them.
+    pub fn verify_manifest(&self) -> io::Result<()> {
+        let result = self.check_manifest();
+        if let Some(audit_log) = &self.audit_log {
+            let details = match &result {
+                Ok(details) => details.clone(),
+                Err(e) => json!({ "result": "failed", "error": e.to_string() }),
+            };
+            audit_log.record_or_warn("verification", &self.model_name, details);
         }
+        result.map(|_| ())
+    }
+
+    /// Does the work of `verify_manifest`, returning what was checked for the audit log
+    fn check_manifest(&self) -> io::Result<Value> {
+        let model_dir = self.model_path.join(&self.model_name);
+        let manifest_path = model_dir.join(MANIFEST_FILE);
+        // Read once: the signature is checked over, and the digests taken from, the same bytes
+        let manifest_bytes = match std::fs::read(&manifest_path) {
+            Ok(bytes) => Some(bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let Some(manifest_bytes) = manifest_bytes else {
+            if self.trusted_keys.is_some() {
+                eprintln!("❌ Model '{}' has no signed manifest", self.model_name);
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Model '{}' has no hash manifest to verify", self.model_name),
+                ));
+            }
+            println!("⚠️ No hash manifest for model '{}'", self.model_name);
+            return Ok(json!({ "result": "no_manifest" }));
+        };
+
+        let mut signer = None;
+        if let Some(trusted_keys) = &self.trusted_keys {
+            match verify_manifest_signature(&model_dir, &manifest_bytes, trusted_keys) {
+                Ok(key) => {
+                    let key = hex::encode(key.as_bytes());
+                    println!("✅ Manifest signed by trusted key {}", key);
+                    signer = Some(key);
+                }
+                Err(e) => {
+                    eprintln!("❌ Refusing to load model '{}': {}", self.model_name, e);
+                    return Err(e);
+                }
+            }
+        }
+
+        let manifest = Manifest::from_bytes(&manifest_bytes)?;
+        // The signature covers the manifest, so this binds the files to the model's name too
+        if manifest.model_name != self.model_name {
+            eprintln!(
+                "❌ Refusing to load model '{}': its manifest is for '{}'",
+                self.model_name, manifest.model_name
+            );
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Manifest is for model '{}', not '{}'",
+                    manifest.model_name, self.model_name
+                ),
+            ));
+        }
+        let report = manifest.verify(&model_dir)?;
+        if report.is_clean() {
+            println!(
+                "✅ Model '{}' matches its {} manifest ({} files)",
+                self.model_name,
+                manifest.algorithm.name(),
+                manifest.files.len()
+            );
+            Ok(json!({
+                "result": "verified",
+                "algorithm": manifest.algorithm.name(),
+                "files": manifest.files.len(),
+                "signer": signer,
+            }))
+        } else {
+            eprintln!(
+                "❌ Model '{}' does not match its manifest:",
+                self.model_name
+            );
+            eprintln!("  {}", report);
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Model '{}' does not match its manifest ({})",
+                    self.model_name, report
+                ),
+            ))
+        }
+    }
+
+    /// Appends an event for this model to the audit log, if one is configured
+    fn audit(&self, event: &str, details: Value) {
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record_or_warn(event, &self.model_name, details);
+        }
+    }
+
+    /// Signs and logs a receipt for one inference, if receipts are enabled
+    fn issue_receipt(
+        &self,
+        inputs_sha256: String,
+        outputs: &Value,
+        started_at_ms: u64,
+        finished_at_ms: u64,
+    ) -> io::Result<Option<InferenceReceipt>> {
+        let (Some(config), Some(model_digest)) = (&self.receipts, &self.model_digest) else {
+            return Ok(None);
+        };
+        let receipt = InferenceReceipt {
+            model_name: self.model_name.clone(),
+            model_digest: model_digest.clone(),
+            inputs_sha256,
+            outputs_sha256: hash_outputs(outputs),
+            started_at_ms,
+            finished_at_ms,
+            endpoint: self.url.clone(),
+            node_key: String::new(),
+            signature: String::new(),
+        }
+        .signed(&config.signing_key);
+        receipt.append_to(&config.log_path)?;
+        println!("🧾 Receipt written to {:?}", config.log_path);
+        Ok(Some(receipt))
     }
 
     // Unload a model from Triton
     pub async fn unload_model(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let url = format!("{}/repository/models/{}/unload", self.url, self.model_name);
+        let path = format!("/repository/models/{}/unload", self.model_name);
         let response = self
-            .client
-            .post(&url)
-            .json(&serde_json::json!({}))
-            .send()
+            .transport
+            .post(&self.url, &path, &serde_json::json!({}))
             .await?;
+        self.audit("unload", json!({ "status": response.status }));
 
-        if response.status().is_success() {
+        if response.is_success() {
             println!("✅ Successfully unloaded model: {}", self.model_name);
             Ok(())
         } else {
             Err(format!(
                 "Failed to unload model '{}'. HTTP Status: {:?}",
-                self.model_name,
-                response.status()
+                self.model_name, response.status
             )
             .into())
         }
     }
 
+    /// Names of the models the server has loaded or is loading, from its repository index
+    pub async fn loaded_models(
+        &self,
+    ) -> Result<HashSet<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let response = self
+            .transport
+            .post(&self.url, "/repository/index", &json!({}))
+            .await?;
+        if !response.is_success() {
+            return Err(format!(
+                "Failed to fetch repository index. HTTP Status: {:?}",
+                response.status
+            )
+            .into());
+        }
+        let index: Vec<Value> = response.json()?;
+        Ok(index
+            .iter()
+            // Anything but an unloaded or unloading model may be in use, including one still loading
+            .filter(|model| {
+                model["state"]
+                    .as_str()
+                    .is_some_and(|state| state != "UNAVAILABLE" && state != "UNLOADING")
+            })
+            .filter_map(|model| model["name"].as_str().map(str::to_string))
+            .collect())
+    }
+
+    /// Marks this model as used and evicts others over the quota; failures are only reported
+    async fn enforce_repository_quota(&self, repository: &RepositoryManager) {
+        self.record_use();
+        let mut loaded = match self.loaded_models().await {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                println!(
+                    "⚠️ Skipping quota enforcement, loaded models unknown: {}",
+                    e
+                );
+                return;
+            }
+        };
+        loaded.insert(self.model_name.clone());
+        match repository.enforce_quota(&loaded) {
+            Ok(evicted) if !evicted.is_empty() => {
+                self.audit("eviction", json!({ "evicted": evicted }));
+            }
+            Ok(_) => {}
+            Err(e) => println!("⚠️ Failed to enforce repository quota: {}", e),
+        }
+    }
+
+    /// Marks this model as used in the repository, if one is configured
+    fn record_use(&self) {
+        if let Some(repository) = &self.repository {
+            if let Err(e) = repository.touch(&self.model_name) {
+                println!("⚠️ Failed to record model use: {}", e);
+            }
+        }
+    }
+
     /// Fetches the metadata of a model from Triton Inference Server
     pub async fn get_model_metadata(
         &self,
     ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-        let url = format!("{}/models/{}", self.url, self.model_name);
+        let path = format!("/models/{}", self.model_name);
 
         println!("⏳ Fetching metadata for model: {}", self.model_name);
 
-        let response = self.client.get(&url).send().await?;
+        let response = self.transport.get(&self.url, &path).await?;
 
-        if response.status().is_success() {
-            let metadata: Value = response.json().await?;
+        if response.is_success() {
+            let metadata: Value = response.json()?;
             Ok(metadata)
         } else {
-            println!(
-                "❌ Failed to fetch metadata. Status: {:?}",
-                response.status()
-            );
+            println!("❌ Failed to fetch metadata. Status: {:?}", response.status);
             Err(format!(
                 "❌ Failed to fetch metadata for model '{}'. HTTP Status: {:?}",
-                self.model_name,
-                response.status()
+                self.model_name, response.status
             )
             .into())
         }
@@ -241,17 +625,17 @@ impl TritonClient {
     ) -> Result<HashMap<String, (TensorData, Vec<usize>)>, Box<dyn std::error::Error + Send + Sync>>
     {
         // Fetch model metadata
-        let metadata_url = format!("{}/models/{}", self.url, self.model_name);
-        let metadata_response = self.client.get(&metadata_url).send().await?;
+        let metadata_path = format!("/models/{}", self.model_name);
+        let metadata_response = self.transport.get(&self.url, &metadata_path).await?;
 
-        if !metadata_response.status().is_success() {
-            let error_message = metadata_response.text().await.unwrap_or_default();
+        if !metadata_response.is_success() {
+            let error_message = metadata_response.body;
             return Err(
                 format!("❌ Failed to fetch model metadata: HTTP- {}", error_message).into(),
             );
         }
 
-        let metadata: serde_json::Value = metadata_response.json().await?;
+        let metadata: serde_json::Value = metadata_response.json()?;
         let model_inputs = metadata["inputs"]
             .as_array()
             .ok_or("❌ Invalid model metadata format: 'inputs' not found")?;
@@ -302,21 +686,17 @@ impl TritonClient {
         &self,
         input_data: HashMap<&str, (TensorData, Vec<usize>)>,
     ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
-        let model_inputs: Vec<_> = input_data
-            .iter()
+        // Sort by input name so identical requests serialize identically (cassette replay relies on it)
+        let mut named_inputs: Vec<_> = input_data.iter().collect();
+        named_inputs.sort_by_key(|(name, _)| **name);
+
+        let model_inputs: Vec<_> = named_inputs
+            .into_iter()
             .map(|(name, (tensor_data, shape))| {
-                let datatype = match tensor_data {
-                    TensorData::F32(_) => "FP32",
-                    TensorData::I32(_) => "INT32",
-                    TensorData::I64(_) => "INT64",
-                    TensorData::U8(_) => "UINT8",
-                    TensorData::Bool(_) => "BOOL",
-                    TensorData::Str(_) => "BYTES",
-                };
                 serde_json::json!({
                     "name": name,
                     "shape": shape,
-                    "datatype": datatype,
+                    "datatype": tensor_data.datatype(),
                     "data": tensor_data.to_serializable()
                 })
             })
@@ -324,67 +704,284 @@ impl TritonClient {
 
         let request_body = serde_json::json!({ "inputs": model_inputs });
 
-        let url = format!("{}/models/{}/infer", self.url, self.model_name);
-        let response = self.client.post(&url).json(&request_body).send().await?;
+        let path = format!("/models/{}/infer", self.model_name);
+        let response = self.transport.post(&self.url, &path, &request_body).await?;
 
-        if response.status().is_success() {
-            let result = response.json::<serde_json::Value>().await?;
+        if response.is_success() {
+            let result = response.json::<serde_json::Value>()?;
             Ok(result)
         } else {
-            let error_message = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
+            let error_message = response.body;
             Err(format!("❌ Inference failed: HTTP - {}", error_message).into())
         }
     }
 
+    /// Answers requests from `request_stream` until it ends, `cancel` fires or the task timeout passes.
+    ///
+    /// Requests are answered one at a time unless `concurrency` is set.
+    /// Cancellation and the deadline also cut short the requests in flight; the
+    /// model is unloaded either way before the error is returned.
     pub async fn run<S, C, CFut>(
         &self,
         mut request_stream: S,
         mut response_closure: C,
+        cancel: CancellationToken,
     ) -> Result<(), Box<dyn std::error::Error>>
     where
         S: Stream<Item = String> + Unpin + Send + 'static,
         C: FnMut(String) -> CFut + Send + 'static,
         CFut: Future<Output = ()> + Send + 'static,
     {
-        while let Some(request) = request_stream.next().await {
-            println!("📥 Received inference request");
+        // Fires when the caller cancels or the deadline passes, whichever comes first
+        let task = cancel.child_token();
+        let deadline = self.task_timeout.map(|timeout| {
+            let task = task.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(timeout).await;
+                task.cancel();
+            })
+        });
+        // Whether the model may still be loaded; it only clears once an unload succeeds
+        let mut model_loaded = true;
+        // Whether the run has to unload at the end even when it was not interrupted
+        let mut unload_due = self.concurrency.is_some();
 
-            // Attempt to parse the request string into HashMap<String, TensorData>
-            let parsed_inputs: Result<HashMap<String, TensorData>, _> =
-                serde_json::from_str(&request);
+        if let Some(concurrency) = self.concurrency {
+            self.run_concurrent(request_stream, &mut response_closure, concurrency, &task)
+                .await;
+        } else {
+            loop {
+                let request = tokio::select! {
+                    biased;
+                    _ = task.cancelled() => break,
+                    request = request_stream.next() => match request {
+                        Some(request) => request,
+                        None => break,
+                    },
+                };
+                println!("📥 Received inference request");
 
-            let result: Result<Value, Box<dyn std::error::Error + Send + Sync>> =
-                match parsed_inputs {
+                let result = match parse_request(&request) {
                     Ok(inputs) => {
-                        println!("✅ Successfully parsed inputs.");
-                        self.run_inference(inputs).await
-                    }
-                    Err(e) => {
-                        println!("❌ Failed to parse inputs: {}", e);
-                        Err(format!("Invalid input format: {}", e).into())
+                        let (outcome, unloaded) = self.infer_then_unload(inputs, &task).await;
+                        model_loaded = unloaded.is_err();
+                        if let Err(e) = unloaded {
+                            println!("⚠️ Failed to unload model, retrying after the run: {}", e);
+                            unload_due = true;
+                        }
+                        outcome.map(|(result, _)| result)
                     }
+                    Err(e) => Err(e),
                 };
 
-            // Convert the result to JSON string for output
-            let response = match result {
-                Ok(json) => json.to_string(),
-                Err(e) => format!("❌ Inference error: {}", e),
+                let response = format_response(result);
+                println!("📤 Sending inference response: {}", response);
+                response_closure(response).await;
+            }
+        }
+        if let Some(deadline) = deadline {
+            deadline.abort();
+        }
+
+        let interrupted = task.is_cancelled();
+        let mut unload_error = None;
+        if model_loaded && (interrupted || unload_due) {
+            if let Err(e) = self.unload_model().await {
+                println!("⚠️ Failed to unload model at the end of the run: {}", e);
+                unload_error = Some(e.to_string());
+            }
+        }
+        if self.wipe_after_task {
+            self.wipe_model_dir()?;
+        }
+        // Only reported once the model directory is wiped
+        if let (Some(e), false) = (unload_error, interrupted) {
+            return Err(e.into());
+        }
+        if interrupted {
+            let reason = if cancel.is_cancelled() {
+                "Task cancelled"
+            } else {
+                "Task deadline exceeded"
             };
+            println!("⏹️ {}", reason);
+            self.audit("interrupted", json!({ "reason": reason }));
+            return Err(reason.into());
+        }
+        Ok(())
+    }
 
+    /// Answers up to `max_in_flight` requests at a time, leaving the model loaded
+    async fn run_concurrent<S, C, CFut>(
+        &self,
+        request_stream: S,
+        response_closure: &mut C,
+        concurrency: Concurrency,
+        task: &CancellationToken,
+    ) where
+        S: Stream<Item = String> + Unpin + Send,
+        C: FnMut(String) -> CFut,
+        CFut: Future<Output = ()>,
+    {
+        let limit = concurrency.max_in_flight.max(1);
+        println!(
+            "🔀 Answering up to {} requests at a time ({:?})",
+            limit, concurrency.order
+        );
+        let pending = request_stream.map(|request| async move {
+            println!("📥 Received inference request");
+            let result = match parse_request(&request) {
+                Ok(inputs) => self
+                    .infer_with_receipt(inputs)
+                    .await
+                    .map(|(result, _)| result),
+                Err(e) => Err(e),
+            };
+            format_response(result)
+        });
+        // Both adapters only pull the next request once fewer than `limit` are in flight
+        let mut responses: Pin<Box<dyn Stream<Item = String> + Send + '_>> = match concurrency.order
+        {
+            ResponseOrder::Ordered => Box::pin(pending.buffered(limit)),
+            ResponseOrder::Unordered => Box::pin(pending.buffer_unordered(limit)),
+        };
+
+        loop {
+            let response = tokio::select! {
+                biased;
+                _ = task.cancelled() => break,
+                response = responses.next() => match response {
+                    Some(response) => response,
+                    None => break,
+                },
+            };
             println!("📤 Sending inference response: {}", response);
             response_closure(response).await;
         }
+    }
 
-        Ok(())
+    /// Directory the model is extracted to
+    pub fn model_dir(&self) -> PathBuf {
+        self.model_path.join(&self.model_name)
+    }
+
+    /// Overwrites and removes the extracted model directory.
+    ///
+    /// `run` does this itself when `wipe_after_task` is set; callers driving
+    /// `run_inference` directly call it once they are done with the model.
+    pub fn wipe_model_dir(&self) -> io::Result<WipeStats> {
+        let model_dir = self.model_dir();
+        println!("🧹 Wiping model directory {:?}", model_dir);
+        let result = wipe_dir(&model_dir);
+        self.audit(
+            "wipe",
+            match &result {
+                Ok(stats) => {
+                    json!({ "status": "Wiped", "files": stats.files, "bytes": stats.bytes })
+                }
+                Err(e) => json!({ "status": "Failed", "error": e.to_string() }),
+            },
+        );
+        result
+    }
+
+    /// Unloads the model and wipes its directory once a task is done with it.
+    ///
+    /// A failed unload does not stop the wipe; it shows up as `unloaded: false`.
+    pub async fn cleanup(&self) -> Result<CleanupReport, Box<dyn std::error::Error + Send + Sync>> {
+        let unloaded = match self.unload_model().await {
+            Ok(()) => true,
+            Err(e) => {
+                println!("⚠️ Unload failed during cleanup: {}", e);
+                false
+            }
+        };
+
+        let model_dir = self.model_dir();
+        println!("🧹 Wiping model directory {:?}", model_dir);
+        let stats = match wipe_dir(&model_dir) {
+            Ok(stats) => stats,
+            Err(e) => {
+                self.audit(
+                    "cleanup",
+                    json!({ "unloaded": unloaded, "status": "Failed", "error": e.to_string() }),
+                );
+                return Err(e.into());
+            }
+        };
+
+        let report = CleanupReport {
+            unloaded,
+            files_wiped: stats.files,
+            bytes_wiped: stats.bytes,
+        };
+        self.audit("cleanup", json!(report));
+        println!("✅ Cleaned up model '{}'", self.model_name);
+        Ok(report)
     }
 
+    /// Runs one inference and unloads the model, giving up early if `cancel` fires.
+    ///
+    /// A failed unload is logged rather than returned, so it does not replace the result.
     pub async fn run_inference(
         &self,
         inputs: HashMap<String, TensorData>,
+        cancel: &CancellationToken,
     ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        self.run_inference_with_receipt(inputs, cancel)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    /// Runs inference like `run_inference`, also returning the signed receipt when receipts are enabled
+    pub async fn run_inference_with_receipt(
+        &self,
+        inputs: HashMap<String, TensorData>,
+        cancel: &CancellationToken,
+    ) -> Result<
+        (serde_json::Value, Option<InferenceReceipt>),
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        let (outcome, unloaded) = self.infer_then_unload(inputs, cancel).await;
+        // The inference already happened; a failed unload does not take its result away
+        if let Err(e) = unloaded {
+            println!("⚠️ Failed to unload model after inference: {}", e);
+        }
+        outcome
+    }
+
+    /// Runs one inference, then unloads, returning both outcomes
+    async fn infer_then_unload(
+        &self,
+        inputs: HashMap<String, TensorData>,
+        cancel: &CancellationToken,
+    ) -> (
+        Result<(Value, Option<InferenceReceipt>), Box<dyn std::error::Error + Send + Sync>>,
+        Result<(), Box<dyn std::error::Error + Send + Sync>>,
+    ) {
+        let outcome = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Err("❌ Inference cancelled".into()),
+            outcome = self.infer_with_receipt(inputs) => outcome,
+        };
+        // Also after a cancelled request, so an abandoned inference does not keep the model loaded
+        let unloaded = self.unload_model().await;
+        (outcome, unloaded)
+    }
+
+    /// Runs one inference on the loaded model and leaves it loaded.
+    ///
+    /// For callers running several inferences in a row, who unload once at the end.
+    pub async fn infer_with_receipt(
+        &self,
+        inputs: HashMap<String, TensorData>,
+    ) -> Result<
+        (serde_json::Value, Option<InferenceReceipt>),
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        let inputs_sha256 = hash_inputs(&inputs);
+        // Eviction order follows inferences, not just loads
+        self.record_use();
         // Check if the model is already extracted
 
         // Check if the Triton Server is live
@@ -425,18 +1022,39 @@ impl TritonClient {
                     .map(|(k, v)| (k.as_str(), v.clone()))
                     .collect();
 
-                match self.infer(aligned_refs).await {
+                let started_at_ms = unix_millis();
+                let outcome = self.infer(aligned_refs).await;
+                let finished_at_ms = unix_millis();
+                self.audit(
+                    "inference",
+                    match &outcome {
+                        Ok(result) => json!({
+                            "status": "ok",
+                            "inputs_sha256": inputs_sha256,
+                            "outputs_sha256": hash_outputs(result),
+                            "duration_ms": finished_at_ms - started_at_ms,
+                        }),
+                        Err(e) => json!({
+                            "status": "failed",
+                            "inputs_sha256": inputs_sha256,
+                            "error": e.to_string(),
+                        }),
+                    },
+                );
+                match outcome {
                     Ok(result) => {
                         println!("Inference Successful: {:#?}", result);
                         // println!("-------------------------------------------");
                         // println!("-------------------------------------------");
-                        self.unload_model().await?;
-                        Ok(result)
-                    }
-                    Err(e) => {
-                        self.unload_model().await?;
-                        Err(format!("❌ Inference failed: {:?}", e).into())
+                        let receipt = self.issue_receipt(
+                            inputs_sha256,
+                            &result,
+                            started_at_ms,
+                            finished_at_ms,
+                        )?;
+                        Ok((result, receipt))
                     }
+                    Err(e) => Err(format!("❌ Inference failed: {:?}", e).into()),
                 }
             }
             Err(e) => Err(format!("❌ Inference failed: {:?}", e).into()),
@@ -444,6 +1062,30 @@ impl TritonClient {
     }
 }
 
+/// Parses one request line into named input tensors
+fn parse_request(
+    request: &str,
+) -> Result<HashMap<String, TensorData>, Box<dyn std::error::Error + Send + Sync>> {
+    match serde_json::from_str(request) {
+        Ok(inputs) => {
+            println!("✅ Successfully parsed inputs.");
+            Ok(inputs)
+        }
+        Err(e) => {
+            println!("❌ Failed to parse inputs: {}", e);
+            Err(format!("Invalid input format: {}", e).into())
+        }
+    }
+}
+
+/// Response line handed to the `run` closure
+fn format_response(result: Result<Value, Box<dyn std::error::Error + Send + Sync>>) -> String {
+    match result {
+        Ok(json) => json.to_string(),
+        Err(e) => format!("❌ Inference error: {}", e),
+    }
+}
+
 //  pub async fn run_inference(
 //     &self,
 //     inputs: HashMap<String, TensorData>,
@@ -527,3 +1169,289 @@ impl TritonClient {
 // 		Err(e) => Err(format!("❌ Inference failed: {:?}", e).into()),
 // 	    }
 // 	}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{serve, simple_inputs, simple_session, write_cassette, TestResponse};
+    use crate::transport::Transport;
+
+    /// Server for the `simple` model whose inference requests hang for a second
+    async fn hanging_server() -> (
+        String,
+        Arc<std::sync::Mutex<Vec<crate::test_support::TestRequest>>>,
+    ) {
+        let metadata = simple_session()[3].response_body.clone();
+        let (url, seen) = serve(move |request| {
+            if request.path.ends_with("/infer") {
+                std::thread::sleep(Duration::from_secs(1));
+            }
+            if request.path == "/v2/models/simple" {
+                TestResponse::new(200, metadata.clone().into_bytes())
+            } else {
+                TestResponse::new(200, b"{}".to_vec())
+            }
+        })
+        .await;
+        (format!("{}/v2", url), seen)
+    }
+
+    fn unloaded(seen: &std::sync::Mutex<Vec<crate::test_support::TestRequest>>) -> bool {
+        seen.lock()
+            .unwrap()
+            .iter()
+            .any(|request| request.path == "/v2/repository/models/simple/unload")
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cancelled_inference_still_unloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, seen) = hanging_server().await;
+        let client = TritonClient::connect(
+            &url,
+            "simple",
+            dir.path().to_path_buf(),
+            &ClientOptions::default(),
+        );
+
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            trigger.cancel();
+        });
+        let err = client
+            .run_inference(simple_inputs(), &cancel)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("cancelled"), "{}", err);
+        assert!(unloaded(&seen));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_stops_at_task_deadline() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, seen) = hanging_server().await;
+        let options = ClientOptions {
+            task_timeout: Some(Duration::from_millis(150)),
+            ..ClientOptions::default()
+        };
+        let client = TritonClient::connect(&url, "simple", dir.path().to_path_buf(), &options);
+
+        // No request ever arrives; the deadline still ends the task and unloads the model
+        let err = client
+            .run(
+                futures::stream::pending::<String>(),
+                |_| async {},
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Task deadline exceeded");
+        assert!(unloaded(&seen));
+    }
+
+    /// Runs four valid requests around an invalid one, two at a time, and
+    /// returns the responses, the most inferences seen at once and the unload count
+    async fn run_two_at_a_time(order: ResponseOrder) -> (Vec<String>, usize, usize) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let metadata = simple_session()[3].response_body.clone();
+        let infer_response = simple_session()[5].response_body.clone();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+        let (in_flight_seen, most_seen) = (in_flight.clone(), most_in_flight.clone());
+        let (url, seen) = serve(move |request| {
+            if request.path.ends_with("/infer") {
+                let now = in_flight_seen.fetch_add(1, Ordering::SeqCst) + 1;
+                most_seen.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(200));
+                in_flight_seen.fetch_sub(1, Ordering::SeqCst);
+                TestResponse::new(200, infer_response.clone().into_bytes())
+            } else if request.path == "/v2/models/simple" {
+                TestResponse::new(200, metadata.clone().into_bytes())
+            } else {
+                TestResponse::new(200, b"{}".to_vec())
+            }
+        })
+        .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let options = ClientOptions {
+            concurrency: Some(Concurrency {
+                max_in_flight: 2,
+                order,
+            }),
+            ..ClientOptions::default()
+        };
+        let client = TritonClient::connect(
+            &format!("{}/v2", url),
+            "simple",
+            dir.path().to_path_buf(),
+            &options,
+        );
+
+        let valid = serde_json::to_string(&simple_inputs()).unwrap();
+        let requests = vec![
+            valid.clone(),
+            "not json".to_string(),
+            valid.clone(),
+            valid.clone(),
+            valid,
+        ];
+        let responses = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = responses.clone();
+        client
+            .run(
+                futures::stream::iter(requests),
+                move |response| {
+                    sink.lock().unwrap().push(response);
+                    async {}
+                },
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        let unloads = seen
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.path == "/v2/repository/models/simple/unload")
+            .count();
+        let responses = responses.lock().unwrap().clone();
+        (responses, most_in_flight.load(Ordering::SeqCst), unloads)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_run_keeps_request_order() {
+        let (responses, most_in_flight, unloads) = run_two_at_a_time(ResponseOrder::Ordered).await;
+        assert_eq!(responses.len(), 5);
+        assert!(responses[0].contains("OUTPUT0"), "{}", responses[0]);
+        assert!(responses[1].contains("Invalid input format"));
+        assert!(responses[2..].iter().all(|r| r.contains("OUTPUT0")));
+        assert_eq!(most_in_flight, 2);
+        assert_eq!(unloads, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn unordered_run_answers_fast_requests_first() {
+        let (responses, most_in_flight, unloads) =
+            run_two_at_a_time(ResponseOrder::Unordered).await;
+        assert_eq!(responses.len(), 5);
+        // The invalid request needs no server round trip, so it overtakes the first one
+        assert!(responses[0].contains("Invalid input format"));
+        assert_eq!(most_in_flight, 2);
+        assert_eq!(unloads, 1);
+    }
+
+    #[tokio::test]
+    async fn refused_load_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = simple_session();
+        session.truncate(3);
+        session[2].status = 400;
+        session[2].response_body = json!({ "error": "failed to load 'simple'" }).to_string();
+        let cassette = dir.path().join("refused.cassette");
+        write_cassette(&cassette, &session);
+
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            ..ClientOptions::default()
+        };
+        let mut client = TritonClient::connect(
+            "http://triton.invalid/v2",
+            "simple",
+            dir.path().to_path_buf(),
+            &options,
+        );
+        let err = client.load_model().await.unwrap_err().to_string();
+        assert!(
+            err.contains("400") && err.contains("failed to load"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_run_wipes_even_when_unload_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("simple/1")).unwrap();
+        std::fs::write(dir.path().join("simple/1/model.onnx"), b"onnx").unwrap();
+        let (url, _) = serve(|_| TestResponse::new(500, b"{}".to_vec())).await;
+        let options = ClientOptions {
+            wipe_after_task: true,
+            concurrency: Some(Concurrency {
+                max_in_flight: 2,
+                order: ResponseOrder::Ordered,
+            }),
+            ..ClientOptions::default()
+        };
+        let client = TritonClient::connect(
+            &format!("{}/v2", url),
+            "simple",
+            dir.path().to_path_buf(),
+            &options,
+        );
+
+        let err = client
+            .run(
+                futures::stream::iter(Vec::<String>::new()),
+                |_| async {},
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Failed to unload"), "{}", err);
+        assert!(!dir.path().join("simple").exists());
+    }
+
+    #[tokio::test]
+    async fn failed_unload_keeps_result_and_is_retried() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let metadata = simple_session()[3].response_body.clone();
+        let infer_response = simple_session()[5].response_body.clone();
+        let unloads = Arc::new(AtomicUsize::new(0));
+        let unloads_seen = unloads.clone();
+        let (url, _) = serve(move |request| {
+            if request.path.ends_with("/unload") {
+                // The first unload fails, later ones succeed
+                match unloads_seen.fetch_add(1, Ordering::SeqCst) {
+                    0 => TestResponse::new(500, b"{}".to_vec()),
+                    _ => TestResponse::new(200, b"{}".to_vec()),
+                }
+            } else if request.path.ends_with("/infer") {
+                TestResponse::new(200, infer_response.clone().into_bytes())
+            } else {
+                TestResponse::new(200, metadata.clone().into_bytes())
+            }
+        })
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        let client = TritonClient::connect(
+            &format!("{}/v2", url),
+            "simple",
+            dir.path().to_path_buf(),
+            &ClientOptions::default(),
+        );
+
+        let responses = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = responses.clone();
+        client
+            .run(
+                futures::stream::iter(vec![serde_json::to_string(&simple_inputs()).unwrap()]),
+                move |response| {
+                    sink.lock().unwrap().push(response);
+                    async {}
+                },
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        let responses = responses.lock().unwrap();
+        assert!(responses[0].contains("OUTPUT0"), "{}", responses[0]);
+        assert_eq!(unloads.load(Ordering::SeqCst), 2);
+    }
+}
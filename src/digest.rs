@@ -0,0 +1,357 @@
+use crate::hash::{hash_bytes, hash_file, HashAlgorithm};
+use crate::manifest::MANIFEST_FILE;
+use crate::models::COMPLETION_MARKER;
+use crate::signing::SIGNATURE_FILE;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Canonical digest of a whole model directory.
+///
+/// Every file is hashed on its own and keyed by its `/`-separated path relative
+/// to the model directory. The digest is a Merkle tree mirroring the directory
+/// layout: each directory node is the SHA-256 of its entries in name order, where
+/// a file entry is `sha256("file" || 0x00 || name || 0x00 || file hash)` and a
+/// subdirectory entry is `sha256("dir" || 0x00 || name || 0x00 || node hash)`.
+/// The root is the node of the model directory itself, so renaming, editing,
+/// adding or removing any file changes it, while untouched subdirectories keep
+/// their node hash. Symlinks are covered by their target rather than the file
+/// they point at.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryDigest {
+    pub root: String,
+    pub files: BTreeMap<String, String>,
+}
+
+/// Files whose contents differ between a recorded digest and a directory
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DigestReport {
+    pub modified: Vec<String>,
+    pub missing: Vec<String>,
+    pub added: Vec<String>,
+}
+
+impl DigestReport {
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.added.is_empty()
+    }
+
+    /// Compares recorded per-file digests against freshly computed ones
+    pub(crate) fn compare(
+        expected: &BTreeMap<String, String>,
+        actual: &BTreeMap<String, String>,
+    ) -> Self {
+        let mut report = DigestReport::default();
+        for (path, digest) in expected {
+            match actual.get(path) {
+                Some(current) if current == digest => {}
+                Some(_) => report.modified.push(path.clone()),
+                None => report.missing.push(path.clone()),
+            }
+        }
+        report.added = actual
+            .keys()
+            .filter(|path| !expected.contains_key(*path))
+            .cloned()
+            .collect();
+        report
+    }
+}
+
+impl fmt::Display for DigestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_clean() {
+            return write!(f, "no differences");
+        }
+        let sections = [
+            ("modified", &self.modified),
+            ("missing", &self.missing),
+            ("added", &self.added),
+        ];
+        let parts: Vec<String> = sections
+            .iter()
+            .filter(|(_, paths)| !paths.is_empty())
+            .map(|(label, paths)| format!("{}: {}", label, paths.join(", ")))
+            .collect();
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+impl DirectoryDigest {
+    /// Hashes every file below `dir`, skipping the completion marker, manifest and signature
+    pub fn compute(dir: &Path) -> io::Result<Self> {
+        Ok(Self::from_files(file_digests(dir, HashAlgorithm::Sha256)?))
+    }
+
+    /// Builds the digest from already known per-file hashes
+    pub fn from_files(files: BTreeMap<String, String>) -> Self {
+        let root = Self::node_hashes(&files).remove("").unwrap_or_default();
+        Self { root, files }
+    }
+
+    /// Node hash of every directory in the tree, keyed by its relative path (`""` is the root)
+    pub fn directories(&self) -> BTreeMap<String, String> {
+        Self::node_hashes(&self.files)
+    }
+
+    fn node_hashes(files: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        let mut root = Node::default();
+        for (path, file_hash) in files {
+            root.insert(path, file_hash);
+        }
+        let mut hashes = BTreeMap::new();
+        root.hash("", &mut hashes);
+        hashes
+    }
+
+    /// Recomputes the digest of `dir` and lists every file that differs from this one
+    pub fn verify(&self, dir: &Path) -> io::Result<DigestReport> {
+        let current = file_digests(dir, HashAlgorithm::Sha256)?;
+        Ok(DigestReport::compare(&self.files, &current))
+    }
+
+    /// Writes the digest as pretty-printed JSON
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, json)
+    }
+
+    /// Reads a digest written by `write_to`, rejecting one whose root does not match its files
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        let digest: Self = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if Self::from_files(digest.files.clone()).root != digest.root {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Digest file {:?} is inconsistent with its root", path),
+            ));
+        }
+        Ok(digest)
+    }
+}
+
+/// Top-level files that are never part of a digest
+pub(crate) const BOOKKEEPING_FILES: &[&str] = &[COMPLETION_MARKER, MANIFEST_FILE, SIGNATURE_FILE];
+
+/// One directory of the digest tree
+#[derive(Default)]
+struct Node<'a> {
+    files: BTreeMap<&'a str, &'a str>,
+    dirs: BTreeMap<&'a str, Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn insert(&mut self, path: &'a str, file_hash: &'a str) {
+        match path.split_once('/') {
+            Some((dir, rest)) => self.dirs.entry(dir).or_default().insert(rest, file_hash),
+            None => {
+                self.files.insert(path, file_hash);
+            }
+        }
+    }
+
+    /// Hashes this directory, recording it and every subdirectory under its relative path
+    fn hash(&self, path: &str, hashes: &mut BTreeMap<String, String>) -> String {
+        let mut entries = BTreeMap::new();
+        for (name, file_hash) in &self.files {
+            entries.insert(*name, entry_hash("file", name, file_hash));
+        }
+        for (name, dir) in &self.dirs {
+            let child = match path {
+                "" => name.to_string(),
+                _ => format!("{}/{}", path, name),
+            };
+            let node = dir.hash(&child, hashes);
+            entries.insert(*name, entry_hash("dir", name, &node));
+        }
+
+        let mut hasher = Sha256::new();
+        for entry in entries.values() {
+            hasher.update(entry);
+        }
+        let node = hex::encode(hasher.finalize());
+        hashes.insert(path.to_string(), node.clone());
+        node
+    }
+}
+
+fn entry_hash(kind: &str, name: &str, hash: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(kind.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(name.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(hash.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Per-file digests below `dir`, keyed by `/`-separated relative path.
+///
+/// Bookkeeping files written next to the model (completion marker, hash
+/// manifest, signature) are left out, since they describe the content rather than being part of it.
+pub(crate) fn file_digests(
+    dir: &Path,
+    algorithm: HashAlgorithm,
+) -> io::Result<BTreeMap<String, String>> {
+    let mut files = BTreeMap::new();
+    collect_files(dir, "", algorithm, &mut files)?;
+    Ok(files)
+}
+
+fn collect_files(
+    dir: &Path,
+    prefix: &str,
+    algorithm: HashAlgorithm,
+    files: &mut BTreeMap<String, String>,
+) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Non UTF-8 file name {:?} in {:?}", name, dir),
+            )
+        })?;
+        if prefix.is_empty() && BOOKKEEPING_FILES.contains(&name.as_str()) {
+            continue;
+        }
+
+        let relative = format!("{}{}", prefix, name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", relative), algorithm, files)?;
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(entry.path())?;
+            let link = format!("symlink\0{}", target.to_string_lossy());
+            files.insert(relative, hash_bytes(link.as_bytes(), algorithm));
+        } else if file_type.is_file() {
+            files.insert(relative, hash_file(&entry.path(), algorithm)?);
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("'{}' in {:?} is not a regular file", relative, dir),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_model(dir: &Path) {
+        std::fs::create_dir_all(dir.join("1")).unwrap();
+        std::fs::write(dir.join("config.pbtxt"), "name: \"simple\"\n").unwrap();
+        std::fs::write(dir.join("labels.txt"), "cat\ndog\n").unwrap();
+        std::fs::write(dir.join("1/model.onnx"), b"onnx").unwrap();
+        std::fs::write(dir.join("1/weights.bin"), b"weights").unwrap();
+    }
+
+    #[test]
+    fn digest_is_canonical() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        write_model(a.path());
+        write_model(b.path());
+        // The completion marker is bookkeeping, not model content
+        std::fs::write(b.path().join(COMPLETION_MARKER), "archive: x\n").unwrap();
+
+        let digest = DirectoryDigest::compute(a.path()).unwrap();
+        assert_eq!(digest, DirectoryDigest::compute(b.path()).unwrap());
+        assert_eq!(
+            digest.files.keys().collect::<Vec<_>>(),
+            [
+                "1/model.onnx",
+                "1/weights.bin",
+                "config.pbtxt",
+                "labels.txt"
+            ]
+        );
+
+        // Moving content between files must change the root even if the hashes are reused
+        std::fs::rename(b.path().join("labels.txt"), b.path().join("labels2.txt")).unwrap();
+        assert_ne!(
+            DirectoryDigest::compute(b.path()).unwrap().root,
+            digest.root
+        );
+    }
+
+    #[test]
+    fn untouched_directories_keep_their_node() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path());
+        std::fs::create_dir_all(dir.path().join("2")).unwrap();
+        std::fs::write(dir.path().join("2/model.onnx"), b"onnx v2").unwrap();
+        let before = DirectoryDigest::compute(dir.path()).unwrap();
+        let nodes = before.directories();
+        assert_eq!(nodes.keys().collect::<Vec<_>>(), ["", "1", "2"]);
+        assert_eq!(nodes[""], before.root);
+
+        std::fs::write(dir.path().join("2/model.onnx"), b"tampered").unwrap();
+        let after = DirectoryDigest::compute(dir.path()).unwrap().directories();
+        assert_eq!(after["1"], nodes["1"]);
+        assert_ne!(after["2"], nodes["2"]);
+        assert_ne!(after[""], nodes[""]);
+
+        // A file whose hash equals a directory node must not collide with that directory
+        let nested = DirectoryDigest::from_files(BTreeMap::from([(
+            "1/model.onnx".to_string(),
+            before.files["1/model.onnx"].clone(),
+        )]));
+        let flat = DirectoryDigest::from_files(BTreeMap::from([(
+            "1".to_string(),
+            nested.directories()["1"].clone(),
+        )]));
+        assert_ne!(flat.root, nested.root);
+    }
+
+    #[test]
+    fn verify_reports_each_difference() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path());
+        let digest = DirectoryDigest::compute(dir.path()).unwrap();
+        assert!(digest.verify(dir.path()).unwrap().is_clean());
+
+        std::fs::write(dir.path().join("config.pbtxt"), "name: \"evil\"\n").unwrap();
+        std::fs::remove_file(dir.path().join("1/weights.bin")).unwrap();
+        std::fs::write(dir.path().join("1/model.py"), "import os\n").unwrap();
+
+        let report = digest.verify(dir.path()).unwrap();
+        assert_eq!(
+            report,
+            DigestReport {
+                modified: vec!["config.pbtxt".to_string()],
+                missing: vec!["1/weights.bin".to_string()],
+                added: vec!["1/model.py".to_string()],
+            }
+        );
+        assert_eq!(
+            report.to_string(),
+            "modified: config.pbtxt; missing: 1/weights.bin; added: 1/model.py"
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_digest_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let model = dir.path().join("simple");
+        write_model(&model);
+        let path = dir.path().join("simple.digest.json");
+
+        let mut digest = DirectoryDigest::compute(&model).unwrap();
+        digest.write_to(&path).unwrap();
+        assert_eq!(DirectoryDigest::read_from(&path).unwrap(), digest);
+
+        digest
+            .files
+            .insert("config.pbtxt".to_string(), "0".repeat(64));
+        digest.write_to(&path).unwrap();
+        let err = DirectoryDigest::read_from(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,350 @@
+//! AES-256-GCM encrypted model archives.
+//!
+//! An encrypted archive wraps an ordinary tar archive (plain or compressed) in
+//! the STREAM construction (big-endian 32-bit counter), so it can be decrypted
+//! chunk by chunk while it is being extracted:
+//!
+//! ```text
+//! magic          8 bytes    "OIRENC01"
+//! key_id         u16 length (little-endian) + UTF-8, passed to the `KeyProvider`
+//! nonce_prefix   7 bytes    random per archive
+//! chunk_size     u32        plaintext bytes per chunk (little-endian)
+//! chunks         each chunk_size bytes + 16-byte tag; the final one may be shorter
+//! ```
+//!
+//! The header is authenticated as associated data of every chunk, and the
+//! final chunk is marked as such, so truncation and reordering are detected.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// First bytes of every encrypted archive
+pub const ENCRYPTED_MAGIC: &[u8; 8] = b"OIRENC01";
+
+/// File suffix for encrypted archives in the model repository
+pub const ENCRYPTED_EXTENSION: &str = "oirenc";
+
+const NONCE_PREFIX_LEN: usize = 7;
+const TAG_LEN: usize = 16;
+const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+/// Refuse headers asking for absurd chunk buffers
+const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Supplies the AES-256 key for an encrypted archive
+pub trait KeyProvider: Send + Sync {
+    /// Returns the key named by the archive header, or an error if it is unknown
+    fn key(&self, key_id: &str) -> io::Result<[u8; 32]>;
+}
+
+/// Keys held in memory, looked up by id
+#[derive(Clone, Default)]
+pub struct StaticKeyProvider {
+    keys: HashMap<String, [u8; 32]>,
+}
+
+impl StaticKeyProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key(mut self, key_id: &str, key: [u8; 32]) -> Self {
+        self.keys.insert(key_id.to_string(), key);
+        self
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn key(&self, key_id: &str) -> io::Result<[u8; 32]> {
+        self.keys.get(key_id).copied().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("No key for encrypted archive key id '{}'", key_id),
+            )
+        })
+    }
+}
+
+/// Whether `path` starts with the encrypted archive magic
+pub fn is_encrypted(path: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; ENCRYPTED_MAGIC.len()];
+    let mut file = File::open(path)?;
+    match file.read_exact(&mut magic) {
+        Ok(()) => Ok(&magic == ENCRYPTED_MAGIC),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Encrypts the archive at `input` into `output` under `key`, recording `key_id` in the header
+pub fn encrypt_archive(
+    input: &Path,
+    output: &Path,
+    key_id: &str,
+    key: &[u8; 32],
+) -> io::Result<()> {
+    let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+    OsRng.fill_bytes(&mut nonce_prefix);
+    let header = encode_header(key_id, &nonce_prefix, DEFAULT_CHUNK_SIZE)?;
+
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut writer = BufWriter::new(File::create(output)?);
+    writer.write_all(&header)?;
+
+    let mut encryptor =
+        EncryptorBE32::<Aes256Gcm>::new(Key::<Aes256Gcm>::from_slice(key), (&nonce_prefix).into());
+    let mut chunk = vec![0u8; DEFAULT_CHUNK_SIZE as usize];
+    let mut len = read_full(&mut reader, &mut chunk)?;
+    // A chunk is only known to be the last once the following read comes back empty
+    let mut next = vec![0u8; DEFAULT_CHUNK_SIZE as usize];
+    loop {
+        let next_len = if len == chunk.len() {
+            read_full(&mut reader, &mut next)?
+        } else {
+            0
+        };
+        let payload = Payload {
+            msg: &chunk[..len],
+            aad: &header,
+        };
+        if next_len == 0 {
+            let ciphertext = encryptor
+                .encrypt_last(payload)
+                .map_err(|_| crypto_error())?;
+            writer.write_all(&ciphertext)?;
+            break;
+        }
+        let ciphertext = encryptor
+            .encrypt_next(payload)
+            .map_err(|_| crypto_error())?;
+        writer.write_all(&ciphertext)?;
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+    }
+
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+/// Decrypts an encrypted archive as it is read; only authenticated plaintext is returned
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    header: Vec<u8>,
+    decryptor: Option<DecryptorBE32<Aes256Gcm>>,
+    /// Ciphertext of the chunk after the current one, read ahead to spot the last chunk
+    pending: Vec<u8>,
+    plaintext: Vec<u8>,
+    position: usize,
+    chunk_len: usize,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// Reads the header from `inner` and fetches its key from `keys`
+    pub fn new(mut inner: R, keys: &dyn KeyProvider) -> io::Result<Self> {
+        let mut header = vec![0u8; ENCRYPTED_MAGIC.len() + 2];
+        inner.read_exact(&mut header)?;
+        if &header[..ENCRYPTED_MAGIC.len()] != ENCRYPTED_MAGIC {
+            return Err(invalid_archive("missing OIRENC01 header"));
+        }
+        let id_len = u16::from_le_bytes([header[8], header[9]]) as usize;
+        let mut rest = vec![0u8; id_len + NONCE_PREFIX_LEN + 4];
+        inner.read_exact(&mut rest)?;
+        header.extend_from_slice(&rest);
+
+        let key_id = std::str::from_utf8(&rest[..id_len])
+            .map_err(|_| invalid_archive("key id is not UTF-8"))?;
+        let nonce_prefix: [u8; NONCE_PREFIX_LEN] = rest[id_len..id_len + NONCE_PREFIX_LEN]
+            .try_into()
+            .expect("slice has nonce length");
+        let chunk_size = u32::from_le_bytes(
+            rest[id_len + NONCE_PREFIX_LEN..]
+                .try_into()
+                .expect("slice has u32 length"),
+        );
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(invalid_archive("unsupported chunk size"));
+        }
+
+        let key = keys.key(key_id)?;
+        let decryptor = DecryptorBE32::<Aes256Gcm>::new(
+            Key::<Aes256Gcm>::from_slice(&key),
+            (&nonce_prefix).into(),
+        );
+        let chunk_len = chunk_size as usize + TAG_LEN;
+        let mut pending = vec![0u8; chunk_len];
+        let pending_len = read_full(&mut inner, &mut pending)?;
+        pending.truncate(pending_len);
+
+        Ok(Self {
+            inner,
+            header,
+            decryptor: Some(decryptor),
+            pending,
+            plaintext: Vec::new(),
+            position: 0,
+            chunk_len,
+        })
+    }
+
+    /// Decrypts the pending chunk into `plaintext`; returns false once the stream is finished
+    fn decrypt_chunk(&mut self) -> io::Result<bool> {
+        let Some(decryptor) = self.decryptor.as_mut() else {
+            return Ok(false);
+        };
+        let ciphertext = std::mem::take(&mut self.pending);
+        let mut next = vec![0u8; self.chunk_len];
+        let next_len = if ciphertext.len() == self.chunk_len {
+            read_full(&mut self.inner, &mut next)?
+        } else {
+            0
+        };
+        let payload = Payload {
+            msg: &ciphertext,
+            aad: &self.header,
+        };
+
+        self.plaintext = if next_len == 0 {
+            let decryptor = self.decryptor.take().expect("checked above");
+            decryptor.decrypt_last(payload)
+        } else {
+            decryptor.decrypt_next(payload)
+        }
+        .map_err(|_| invalid_archive("authentication failed (wrong key, tampered or truncated)"))?;
+
+        next.truncate(next_len);
+        self.pending = next;
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            if !self.decrypt_chunk()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.plaintext.len() - self.position);
+        buf[..n].copy_from_slice(&self.plaintext[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+fn encode_header(key_id: &str, nonce_prefix: &[u8], chunk_size: u32) -> io::Result<Vec<u8>> {
+    let id_len = u16::try_from(key_id.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Key id too long"))?;
+    let mut header = Vec::new();
+    header.extend_from_slice(ENCRYPTED_MAGIC);
+    header.extend_from_slice(&id_len.to_le_bytes());
+    header.extend_from_slice(key_id.as_bytes());
+    header.extend_from_slice(nonce_prefix);
+    header.extend_from_slice(&chunk_size.to_le_bytes());
+    Ok(header)
+}
+
+/// Fills `buf` as far as the reader allows and returns how many bytes were read
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+fn crypto_error() -> io::Error {
+    io::Error::other("AES-GCM encryption failed")
+}
+
+fn invalid_archive(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid encrypted archive: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [42; 32];
+
+    fn round_trip(plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("plain");
+        let output = dir.path().join("sealed");
+        std::fs::write(&input, plaintext).unwrap();
+        encrypt_archive(&input, &output, "tenant-a", &KEY).unwrap();
+
+        let keys = StaticKeyProvider::new().with_key("tenant-a", KEY);
+        let mut decrypted = Vec::new();
+        DecryptingReader::new(File::open(&output)?, &keys)?.read_to_end(&mut decrypted)?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn round_trips_across_chunk_boundaries() {
+        let chunk = DEFAULT_CHUNK_SIZE as usize;
+        for len in [0, 1, chunk - 1, chunk, chunk + 1, 3 * chunk] {
+            let plaintext: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            assert_eq!(round_trip(&plaintext).unwrap(), plaintext, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_key_truncation_and_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("plain");
+        let output = dir.path().join("sealed");
+        std::fs::write(&input, vec![7u8; DEFAULT_CHUNK_SIZE as usize * 2 + 10]).unwrap();
+        encrypt_archive(&input, &output, "tenant-a", &KEY).unwrap();
+        assert!(is_encrypted(&output).unwrap());
+        assert!(!is_encrypted(&input).unwrap());
+        let sealed = std::fs::read(&output).unwrap();
+
+        let decrypt = |bytes: &[u8], keys: &StaticKeyProvider| -> io::Result<Vec<u8>> {
+            let mut out = Vec::new();
+            DecryptingReader::new(bytes, keys)?.read_to_end(&mut out)?;
+            Ok(out)
+        };
+        let keys = StaticKeyProvider::new().with_key("tenant-a", KEY);
+        assert!(decrypt(&sealed, &keys).is_ok());
+
+        let wrong = StaticKeyProvider::new().with_key("tenant-a", [1; 32]);
+        assert_eq!(
+            decrypt(&sealed, &wrong).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        let unknown = StaticKeyProvider::new();
+        assert_eq!(
+            decrypt(&sealed, &unknown).unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+
+        // Dropping the final chunk leaves a stream whose last chunk is not marked last
+        let truncated = &sealed[..sealed.len() - (10 + TAG_LEN)];
+        assert!(decrypt(truncated, &keys).is_err());
+
+        let mut tampered = sealed.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(decrypt(&tampered, &keys).is_err());
+
+        // The header is authenticated too
+        let mut relabelled = sealed;
+        relabelled[10] = b'b';
+        let keys_b = StaticKeyProvider::new().with_key("benant-a", KEY);
+        assert!(decrypt(&relabelled, &keys_b).is_err());
+    }
+}
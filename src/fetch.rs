@@ -0,0 +1,236 @@
+use reqwest::header::CONTENT_RANGE;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::Path;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Streams a response body into `dest` and returns the SHA-256 of the complete file.
+///
+/// When `dest` already holds bytes from an interrupted attempt, `make_request`
+/// is given a `Range` header value to resume from; it receives `None` for a
+/// fresh download. A resumed response whose `Content-Range` does not start at
+/// that offset is discarded and the download starts over. The partial file is
+/// left in place on errors so the next call can pick up where this one stopped.
+pub async fn download_resumable<F>(dest: &Path, make_request: F) -> io::Result<String>
+where
+    F: Fn(Option<String>) -> RequestBuilder,
+{
+    let mut hasher = Sha256::new();
+    let mut resume_from = 0u64;
+
+    if let Ok(mut existing) = File::open(dest).await {
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = existing.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            resume_from += n as u64;
+        }
+    }
+
+    let request = if resume_from > 0 {
+        println!(
+            "⏯️ Resuming download into {:?} at byte {}",
+            dest, resume_from
+        );
+        make_request(Some(format!("bytes={}-", resume_from)))
+    } else {
+        println!("⬇️ Downloading into {:?}", dest);
+        make_request(None)
+    };
+    let mut response = request.send().await.map_err(io::Error::other)?;
+    if resume_from > 0
+        && response.status() == StatusCode::PARTIAL_CONTENT
+        && content_range_start(&response) != Some(resume_from)
+    {
+        println!(
+            "⚠️ Server sent a different range than byte {} for {:?}, starting over",
+            resume_from, dest
+        );
+        resume_from = 0;
+        response = make_request(None).send().await.map_err(io::Error::other)?;
+    }
+
+    let mut out = match response.status() {
+        StatusCode::PARTIAL_CONTENT if resume_from > 0 => {
+            OpenOptions::new().append(true).open(dest).await?
+        }
+        // Nothing left to fetch: the partial file already holds the whole archive
+        StatusCode::RANGE_NOT_SATISFIABLE if resume_from > 0 => {
+            return Ok(hex::encode(hasher.finalize()));
+        }
+        status if status.is_success() => {
+            hasher = Sha256::new();
+            File::create(dest).await?
+        }
+        status => {
+            let url = response.url().to_string();
+            let body = response.text().await.unwrap_or_default();
+            return Err(io::Error::other(format!(
+                "Download of {} failed. HTTP Status: {:?} {}",
+                url, status, body
+            )));
+        }
+    };
+
+    while let Some(chunk) = response.chunk().await.map_err(io::Error::other)? {
+        hasher.update(&chunk);
+        out.write_all(&chunk).await?;
+    }
+    out.sync_all().await?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// First byte of a `Content-Range: bytes <start>-<end>/<total>` header
+fn content_range_start(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+pub(crate) fn checksum_mismatch(path: &Path, computed: &str, expected: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Checksum mismatch for {:?}: computed {}, expected {}",
+            path,
+            computed,
+            expected.to_lowercase()
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{sha256_file, ExtractionStatus, ModelExtractor};
+    use crate::test_support::{ranged, serve, TestResponse};
+
+    fn simple_zip() -> Vec<u8> {
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("model/simple.zip")).unwrap()
+    }
+
+    fn simple_zip_sha256() -> String {
+        sha256_file(&Path::new(env!("CARGO_MANIFEST_DIR")).join("model/simple.zip")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn fetches_verifies_and_extracts() {
+        let body = simple_zip();
+        let (base_url, _) = serve(move |request| ranged(request, &body)).await;
+        let repo = tempfile::tempdir().unwrap();
+
+        let extractor = ModelExtractor::fetch(
+            &format!("{}/simple.zip", base_url),
+            "simple",
+            repo.path().to_path_buf(),
+            &simple_zip_sha256(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            extractor.ensure_extracted().unwrap(),
+            ExtractionStatus::Extracted
+        );
+        assert!(repo.path().join("simple/1/model.onnx").is_file());
+    }
+
+    #[tokio::test]
+    async fn resumes_partial_download() {
+        let body = simple_zip();
+        let head = body[..1000].to_vec();
+        let (base_url, seen) = serve(move |request| ranged(request, &body)).await;
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::write(repo.path().join(".simple.download"), head).unwrap();
+
+        ModelExtractor::fetch(
+            &format!("{}/simple.zip", base_url),
+            "simple",
+            repo.path().to_path_buf(),
+            &simple_zip_sha256(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read(repo.path().join("simple.zip")).unwrap(),
+            simple_zip()
+        );
+        assert!(!repo.path().join(".simple.download").exists());
+        let requests = seen.lock().unwrap();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/simple.zip");
+        assert_eq!(requests[0].headers["range"], "bytes=1000-");
+    }
+
+    #[tokio::test]
+    async fn restarts_when_server_sends_another_range() {
+        let body = simple_zip();
+        // Answers every range request from byte 0, as a misbehaving server might
+        let (url, seen) = serve(move |request| {
+            let mut response = TestResponse::new(200, body.clone());
+            if request.headers.contains_key("range") {
+                response.status = 206;
+                response.headers.push((
+                    "Content-Range".to_string(),
+                    format!("bytes 0-{}/{}", body.len() - 1, body.len()),
+                ));
+            }
+            response
+        })
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("simple.zip.part");
+        std::fs::write(&dest, &simple_zip()[..1000]).unwrap();
+
+        let client = reqwest::Client::new();
+        let computed = download_resumable(&dest, |range| {
+            let request = client.get(&url);
+            match range {
+                Some(range) => request.header(reqwest::header::RANGE, range),
+                None => request,
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(computed, simple_zip_sha256());
+        assert_eq!(std::fs::read(&dest).unwrap(), simple_zip());
+        let requests = seen.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[1].headers.contains_key("range"));
+    }
+
+    #[tokio::test]
+    async fn rejects_checksum_mismatch() {
+        let body = simple_zip();
+        let (base_url, _) = serve(move |request| ranged(request, &body)).await;
+        let repo = tempfile::tempdir().unwrap();
+
+        let err = ModelExtractor::fetch(
+            &format!("{}/simple.zip", base_url),
+            "simple",
+            repo.path().to_path_buf(),
+            &"0".repeat(64),
+        )
+        .await
+        .err()
+        .unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!repo.path().join("simple.zip").exists());
+        assert!(!repo.path().join(".simple.download").exists());
+    }
+}
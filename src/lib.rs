@@ -1,8 +1,44 @@
+pub mod audit;
+pub mod cleanup;
 pub mod client;
+pub mod digest;
+pub mod encryption;
+pub mod fetch;
+pub mod hash;
+pub mod lock;
+pub mod manifest;
 pub mod models;
+pub mod packer;
+pub mod receipt;
+pub mod repository;
+pub mod signing;
+pub mod source;
+pub mod task;
+pub mod transport;
+pub mod watcher;
 
-pub use client::TritonClient;
-pub use models::ModelExtractor;
+pub use audit::{AuditEntry, AuditLog};
+pub use cleanup::{CleanupGuard, CleanupReport, WipeStats};
+pub use client::{ClientOptions, Concurrency, ResponseOrder, TritonClient};
+pub use digest::{DigestReport, DirectoryDigest};
+pub use encryption::{KeyProvider, StaticKeyProvider};
+pub use hash::{HashAlgorithm, HashProgress};
+pub use lock::ModelLock;
+pub use manifest::Manifest;
+pub use models::{
+    ArchiveFormat, ArchiveRetention, ExtractionLimits, ExtractionStatus, ModelExtractor,
+};
+pub use packer::{ModelPacker, PackManifest};
+pub use receipt::{InferenceReceipt, ReceiptConfig};
+pub use repository::{ModelUsage, RepositoryManager, RepositoryUsage};
+pub use signing::TrustedKeys;
+pub use source::{HttpSource, LocalSource, ModelSource, S3Source};
+pub use task::{run_task, StageResult, TaskResult, TaskSpec};
+pub use transport::Transport;
+pub use watcher::{DropWatcher, WatchEvent, WatchStage};
+
+#[cfg(test)]
+mod test_support;
 
 // #[cfg(test)]
 // mod tests;
@@ -0,0 +1,100 @@
+//! Cross-process locking of model directories.
+//!
+//! Workers sharing a model repository take a `ModelLock` before extracting a
+//! model, so only one of them writes it while the others wait and then find it
+//! already present. Eviction takes the same lock, without waiting, to skip
+//! models that are being extracted.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long `ModelExtractor` waits for another process to finish extracting
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(600);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Exclusive advisory lock on a model, held across processes until dropped.
+///
+/// The lock lives on `.<model>.lock` next to the model directory rather than
+/// inside it, since extraction replaces the directory. The lock file itself is
+/// left in place: removing it would let a waiting process lock a file nobody
+/// else can see any more.
+#[derive(Debug)]
+pub struct ModelLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl ModelLock {
+    /// Path of the lock file for `model_name` under `base_path`
+    pub fn path_for(base_path: &Path, model_name: &str) -> PathBuf {
+        base_path.join(format!(".{}.lock", model_name))
+    }
+
+    /// Takes the lock for `model_name`, waiting up to `timeout` for the current holder.
+    ///
+    /// The wait blocks the calling thread; async code calls this through `spawn_blocking`.
+    pub fn acquire(base_path: &Path, model_name: &str, timeout: Duration) -> io::Result<Self> {
+        std::fs::create_dir_all(base_path)?;
+        let path = Self::path_for(base_path, model_name);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        let started = Instant::now();
+        let mut waiting = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { file, path }),
+                Err(TryLockError::Error(e)) => return Err(e),
+                Err(TryLockError::WouldBlock) => {}
+            }
+            if started.elapsed() >= timeout {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("Timed out after {:?} waiting for lock {:?}", timeout, path),
+                ));
+            }
+            if !waiting {
+                println!("⏳ Waiting for another process holding {:?}", path);
+                waiting = true;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ModelLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_holder_waits_then_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let held = ModelLock::acquire(dir.path(), "simple", Duration::ZERO).unwrap();
+        assert!(held.path().is_file());
+
+        let err = ModelLock::acquire(dir.path(), "simple", Duration::from_millis(250)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // Other models are not affected
+        ModelLock::acquire(dir.path(), "other", Duration::ZERO).unwrap();
+
+        drop(held);
+        ModelLock::acquire(dir.path(), "simple", Duration::ZERO).unwrap();
+    }
+}
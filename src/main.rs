@@ -1,12 +1,10 @@
+use open_inference_runtime::audit::AuditLog;
+use open_inference_runtime::client::*;
+use open_inference_runtime::task::{run_task, TaskSpec};
 use std::collections::HashMap;
-use std::error::Error;
-use tokio;
-mod client;
-use client::*;
-mod models;
-use models::*;
-use std::path::PathBuf;
-use tokio_stream::{self as stream, StreamExt};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tokio_util::sync::CancellationToken;
 
 // const TRITON_URL: &str = "http://localhost:8000/v2";
 
@@ -129,10 +127,84 @@ use tokio_stream::{self as stream, StreamExt};
 
 // }
 
+/// `verify-audit <path> [head]`: checks the hash chain of an audit log, and that it ends at `head` if given
+fn verify_audit(path: &str, head: Option<&String>) -> ExitCode {
+    let verified = match head {
+        Some(head) => AuditLog::verify_head(Path::new(path), head),
+        None => AuditLog::verify(Path::new(path)),
+    };
+    match verified {
+        Ok(entries) => {
+            println!("✅ Audit log {} is intact ({} entries)", path, entries);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `run-task <file>`: runs a task file and prints its result as JSON
+async fn run_task_file(path: &str) -> ExitCode {
+    let spec = match TaskSpec::load(Path::new(path)) {
+        Ok(spec) => spec,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = run_task(&spec, ClientOptions::default()).await;
+    // Stdout carries the runner's progress log, so the result goes to its own file
+    let result_path = spec
+        .result
+        .clone()
+        .unwrap_or_else(|| Path::new(path).with_extension("result.json"));
+    match result.write_to(&result_path) {
+        Ok(()) => println!("📄 Task result written to {:?}", result_path),
+        Err(e) => {
+            eprintln!("❌ Failed to write task result {:?}: {}", result_path, e);
+            return ExitCode::FAILURE;
+        }
+    }
+    if result.success {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
 ///var/lib/cyborg/miner/current_task/model_archive.tar.gz
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("verify-audit") => {
+            return match args.get(2) {
+                Some(path) => verify_audit(path, args.get(3)),
+                None => {
+                    eprintln!("Usage: {} verify-audit <path> [head]", args[0]);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Some("run-task") => {
+            return match args.get(2) {
+                Some(path) => run_task_file(path).await,
+                None => {
+                    eprintln!("Usage: {} run-task <task.json>", args[0]);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Some(command) => {
+            eprintln!("Unknown command '{}'", command);
+            return ExitCode::FAILURE;
+        }
+        None => {}
+    }
+
     // Configurations
     let triton_url = "http://localhost:8000/v2";
     let model_name = "densenet_onnx";
@@ -143,7 +215,7 @@ async fn main() {
         Ok(c) => c,
         Err(e) => {
             eprintln!("❌ Failed to create Triton client: {:?}", e);
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
@@ -154,7 +226,11 @@ async fn main() {
         TensorData::F32(vec![0.1; 3 * 224 * 224]),
     );
 
-    client.run_inference(input_data).await.unwrap();
+    client
+        .run_inference(input_data, &CancellationToken::new())
+        .await
+        .unwrap();
+    ExitCode::SUCCESS
 
     // // Serialize to JSON string to simulate a real WebSocket message
     // let json_string = match serde_json::to_string(&input_data) {
@@ -0,0 +1,348 @@
+//! Versioned binary hash manifest stored next to an extracted model.
+//!
+//! Layout of version 1; integers are little-endian, strings are UTF-8 prefixed
+//! with their byte length as a `u16`:
+//!
+//! ```text
+//! magic          13 bytes   "OIR_MANIFEST\0"
+//! version        u16        1
+//! algorithm      u8         1 = SHA-256, 2 = BLAKE3
+//! created_at     u64        seconds since the Unix epoch
+//! model_name     string
+//! model_version  string     highest numeric version directory, empty if none
+//! file_count     u32
+//! file_count times:
+//!   path         string     `/`-separated, relative to the model directory
+//!   digest       32 bytes
+//! ```
+//!
+//! Paths are strictly ascending, so a model has exactly one encoding. Parsing
+//! rejects unknown versions and algorithms, truncated input and trailing bytes.
+
+use crate::digest::{file_digests, DigestReport};
+use crate::hash::HashAlgorithm;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the manifest inside the model directory
+pub const MANIFEST_FILE: &str = ".oir_manifest";
+
+const MAGIC: &[u8; 13] = b"OIR_MANIFEST\0";
+const FORMAT_VERSION: u16 = 1;
+const DIGEST_LEN: usize = 32;
+
+/// Per-file digests of a model directory plus the metadata needed to check them
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    pub algorithm: HashAlgorithm,
+    pub created_at: u64,
+    pub model_name: String,
+    pub model_version: String,
+    /// Hex digests keyed by relative path
+    pub files: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Hashes every file in `dir` (bookkeeping files excluded) with `algorithm`
+    pub fn for_directory(
+        dir: &Path,
+        model_name: &str,
+        algorithm: HashAlgorithm,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            algorithm,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            model_name: model_name.to_string(),
+            model_version: latest_version(dir)?.unwrap_or_default(),
+            files: file_digests(dir, algorithm)?,
+        })
+    }
+
+    /// Recomputes the digests of `dir` and lists every file that differs
+    pub fn verify(&self, dir: &Path) -> io::Result<DigestReport> {
+        let current = file_digests(dir, self.algorithm)?;
+        Ok(DigestReport::compare(&self.files, &current))
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.push(algorithm_id(self.algorithm));
+        out.extend_from_slice(&self.created_at.to_le_bytes());
+        write_string(&mut out, &self.model_name)?;
+        write_string(&mut out, &self.model_version)?;
+
+        let count = u32::try_from(self.files.len())
+            .map_err(|_| invalid("too many files for a manifest".to_string()))?;
+        out.extend_from_slice(&count.to_le_bytes());
+        for (path, digest) in &self.files {
+            check_path(path)?;
+            write_string(&mut out, path)?;
+            let raw = hex::decode(digest)
+                .ok()
+                .filter(|raw| raw.len() == DIGEST_LEN)
+                .ok_or_else(|| invalid(format!("bad digest for '{}'", path)))?;
+            out.extend_from_slice(&raw);
+        }
+        Ok(out)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.take(MAGIC.len(), "header")? != MAGIC {
+            return Err(invalid("missing OIR_MANIFEST header".to_string()));
+        }
+        let version = reader.u16("version")?;
+        if version != FORMAT_VERSION {
+            return Err(invalid(format!("unsupported version {}", version)));
+        }
+        let algorithm = match reader.u8("algorithm")? {
+            1 => HashAlgorithm::Sha256,
+            2 => HashAlgorithm::Blake3,
+            id => return Err(invalid(format!("unknown hash algorithm id {}", id))),
+        };
+        let created_at = reader.u64("creation time")?;
+        let model_name = reader.string("model name")?;
+        let model_version = reader.string("model version")?;
+
+        let count = reader.u32("file count")?;
+        let mut files = BTreeMap::new();
+        let mut previous: Option<String> = None;
+        for _ in 0..count {
+            let path = reader.string("file path")?;
+            check_path(&path)?;
+            if previous.as_ref().is_some_and(|previous| *previous >= path) {
+                return Err(invalid(format!("'{}' is out of order or repeated", path)));
+            }
+            let digest = hex::encode(reader.take(DIGEST_LEN, "file digest")?);
+            previous = Some(path.clone());
+            files.insert(path, digest);
+        }
+
+        if reader.pos != data.len() {
+            return Err(invalid(format!(
+                "{} trailing bytes",
+                data.len() - reader.pos
+            )));
+        }
+        Ok(Self {
+            algorithm,
+            created_at,
+            model_name,
+            model_version,
+            files,
+        })
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_bytes()?)
+    }
+
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+fn algorithm_id(algorithm: HashAlgorithm) -> u8 {
+    match algorithm {
+        HashAlgorithm::Sha256 => 1,
+        HashAlgorithm::Blake3 => 2,
+    }
+}
+
+/// Highest numeric directory directly below `dir`, as Triton would serve by default
+fn latest_version(dir: &Path) -> io::Result<Option<String>> {
+    let mut latest: Option<u64> = None;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(version) = entry.file_name().to_str().and_then(|n| n.parse().ok()) {
+            latest = latest.max(Some(version));
+        }
+    }
+    Ok(latest.map(|version| version.to_string()))
+}
+
+fn invalid(reason: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid manifest: {}", reason),
+    )
+}
+
+fn check_path(path: &str) -> io::Result<()> {
+    let safe = !path.is_empty()
+        && !path.contains('\0')
+        && path
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..");
+    if safe {
+        Ok(())
+    } else {
+        Err(invalid(format!("unsafe file path {:?}", path)))
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) -> io::Result<()> {
+    let len =
+        u16::try_from(value.len()).map_err(|_| invalid(format!("string too long: {:?}", value)))?;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize, field: &str) -> io::Result<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Truncated manifest: missing {}", field),
+            ));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self, field: &str) -> io::Result<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N, field)?);
+        Ok(out)
+    }
+
+    fn u8(&mut self, field: &str) -> io::Result<u8> {
+        Ok(self.array::<1>(field)?[0])
+    }
+
+    fn u16(&mut self, field: &str) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.array(field)?))
+    }
+
+    fn u32(&mut self, field: &str) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array(field)?))
+    }
+
+    fn u64(&mut self, field: &str) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array(field)?))
+    }
+
+    fn string(&mut self, field: &str) -> io::Result<String> {
+        let len = self.u16(field)? as usize;
+        let bytes = self.take(len, field)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid(format!("{} is not UTF-8", field)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_model(dir: &Path) {
+        std::fs::create_dir_all(dir.join("1")).unwrap();
+        std::fs::create_dir_all(dir.join("3")).unwrap();
+        std::fs::write(dir.join("config.pbtxt"), "name: \"simple\"\n").unwrap();
+        std::fs::write(dir.join("1/model.onnx"), b"onnx").unwrap();
+        std::fs::write(dir.join("3/model.onnx"), b"onnx v3").unwrap();
+    }
+
+    #[test]
+    fn round_trips_and_verifies() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path());
+
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let manifest = Manifest::for_directory(dir.path(), "simple", algorithm).unwrap();
+            manifest.write_to(&dir.path().join(MANIFEST_FILE)).unwrap();
+            let read = Manifest::read_from(&dir.path().join(MANIFEST_FILE)).unwrap();
+
+            assert_eq!(read, manifest);
+            assert_eq!(read.model_version, "3");
+            assert_eq!(read.files.len(), 3);
+            // The manifest file itself is not part of the content it describes
+            assert!(read.verify(dir.path()).unwrap().is_clean());
+        }
+
+        std::fs::write(dir.path().join("1/model.onnx"), b"tampered").unwrap();
+        let manifest = Manifest::read_from(&dir.path().join(MANIFEST_FILE)).unwrap();
+        assert_eq!(
+            manifest.verify(dir.path()).unwrap().modified,
+            ["1/model.onnx"]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path());
+        let bytes = Manifest::for_directory(dir.path(), "simple", HashAlgorithm::Sha256)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        // Every strict prefix is truncated
+        for len in 0..bytes.len() {
+            let err = Manifest::from_bytes(&bytes[..len]).unwrap_err();
+            assert!(
+                matches!(
+                    err.kind(),
+                    io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData
+                ),
+                "prefix of {} bytes: {}",
+                len,
+                err
+            );
+        }
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Manifest::from_bytes(&trailing)
+            .unwrap_err()
+            .to_string()
+            .contains("trailing"));
+
+        let mut future = bytes.clone();
+        future[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&2u16.to_le_bytes());
+        assert!(Manifest::from_bytes(&future)
+            .unwrap_err()
+            .to_string()
+            .contains("unsupported version 2"));
+
+        let mut unknown_algorithm = bytes.clone();
+        unknown_algorithm[MAGIC.len() + 2] = 9;
+        assert!(Manifest::from_bytes(&unknown_algorithm)
+            .unwrap_err()
+            .to_string()
+            .contains("unknown hash algorithm"));
+
+        let mut bad_magic = bytes;
+        bad_magic[0] = b'X';
+        assert!(Manifest::from_bytes(&bad_magic).is_err());
+    }
+
+    #[test]
+    fn rejects_unsafe_paths() {
+        let mut manifest = Manifest::for_directory(
+            tempfile::tempdir().unwrap().path(),
+            "simple",
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        manifest
+            .files
+            .insert("../escape".to_string(), "0".repeat(64));
+        assert!(manifest.to_bytes().is_err());
+    }
+}
@@ -1,70 +1,549 @@
-use base64::{engine::general_purpose, Engine as _};
+use crate::audit::AuditLog;
+use crate::encryption::{is_encrypted, DecryptingReader, KeyProvider, ENCRYPTED_EXTENSION};
+use crate::fetch::checksum_mismatch;
+use crate::hash::{hash_file, HashAlgorithm};
+use crate::lock::{ModelLock, DEFAULT_LOCK_TIMEOUT};
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::signing::SIGNATURE_FILE;
+use crate::source::{HttpSource, ModelSource};
+use bzip2::read::BzDecoder;
 use flate2::read::GzDecoder;
-use sha2::{Digest, Sha256};
+use serde_json::json;
 use std::fs::{remove_file, File};
 use std::io::{self, copy, BufReader, Read, Write};
-use std::path::{Path, PathBuf};
-use tar::Archive;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tar::{Archive, EntryType};
+use xz2::read::XzDecoder;
 use zip::ZipArchive;
 
-/// Handles extraction of model files from a tar.gz or zip archive
+/// Upper bounds enforced while extracting, so a small archive cannot fill the disk
+#[derive(Clone, Debug)]
+pub struct ExtractionLimits {
+    /// Total bytes written across all entries
+    pub max_total_size: u64,
+    /// Bytes written for any single entry
+    pub max_file_size: u64,
+    /// Number of entries (files, directories and links) in the archive
+    pub max_entries: usize,
+    /// Extracted bytes allowed per byte of archive
+    pub max_compression_ratio: u64,
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        Self {
+            max_total_size: 32 * 1024 * 1024 * 1024,
+            max_file_size: 16 * 1024 * 1024 * 1024,
+            max_entries: 10_000,
+            max_compression_ratio: 100,
+        }
+    }
+}
+
+/// Archive file suffixes looked up next to the model, in order of preference
+pub const ARCHIVE_EXTENSIONS: &[&str] = &[
+    "tar.gz",
+    "tgz",
+    "tar.xz",
+    "tar.zst",
+    "tar.bz2",
+    "tar",
+    "zip",
+    ENCRYPTED_EXTENSION,
+];
+
+/// Container and compression of a model archive, as identified by its leading bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    TarBz2,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Identifies the format from magic bytes; the file name is never consulted
+    pub fn detect(path: &Path) -> io::Result<Self> {
+        let mut header = [0u8; 512];
+        let mut file = File::open(path)?;
+        let mut len = 0;
+        while len < header.len() {
+            let read = file.read(&mut header[len..])?;
+            if read == 0 {
+                break;
+            }
+            len += read;
+        }
+        Self::from_magic(&header[..len]).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported archive format: {:?}", path),
+            )
+        })
+    }
+
+    fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveFormat::TarGz)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(ArchiveFormat::TarXz)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(ArchiveFormat::TarZst)
+        } else if header.starts_with(b"BZh") {
+            Some(ArchiveFormat::TarBz2)
+        } else if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            Some(ArchiveFormat::Zip)
+        } else if header.len() >= 262 && &header[257..262] == b"ustar" {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+
+    /// Canonical file suffix for archives of this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarXz => "tar.xz",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::TarBz2 => "tar.bz2",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+/// Hex digits of the archive digest used in cached archive names
+const CACHE_DIGEST_LEN: usize = 16;
+
+/// Written into a model directory once extraction has fully completed.
+///
+/// A model directory without it was left behind by an interrupted extraction.
+pub const COMPLETION_MARKER: &str = ".extraction_complete";
+
+/// Outcome of `ModelExtractor::ensure_extracted`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtractionStatus {
+    /// The model was not present (or only partially) and has been extracted
+    Extracted,
+    /// A complete extraction of the same archive, or a hand-placed model, is already in place
+    AlreadyPresent,
+    /// The archive changed since the last extraction and the model directory was replaced
+    Replaced,
+}
+
+/// What happens to the source archive once the model directory is in place
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ArchiveRetention {
+    /// Remove the archive
+    #[default]
+    Delete,
+    /// Leave the archive where it was found
+    Keep,
+    /// Move the archive into a cache directory
+    MoveToCache(PathBuf),
+    /// Move the archive into a cache directory, keeping only the newest `keep` archives per model.
+    ///
+    /// Keeping none caches nothing: `keep: 0` deletes the archive like `Delete`.
+    KeepLastN { cache_dir: PathBuf, keep: usize },
+}
+
+impl ArchiveRetention {
+    fn cache_dir(&self) -> Option<&Path> {
+        match self {
+            ArchiveRetention::MoveToCache(cache_dir)
+            | ArchiveRetention::KeepLastN { cache_dir, .. } => Some(cache_dir),
+            ArchiveRetention::Delete | ArchiveRetention::Keep => None,
+        }
+    }
+}
+
+/// Handles extraction of model files from a tar (optionally compressed) or zip archive
 pub struct ModelExtractor {
-    archive_path: PathBuf,
+    model_name: String,
+    archive_path: Option<PathBuf>,
     output_folder: PathBuf,
+    limits: ExtractionLimits,
+    retention: ArchiveRetention,
+    rewrite_config_name: bool,
+    hash_algorithm: HashAlgorithm,
+    audit_log: Option<AuditLog>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+    lock_timeout: Duration,
 }
 
 impl ModelExtractor {
     pub fn new(model_name: &str, base_path: PathBuf) -> io::Result<Self> {
-        // let base_path = PathBuf::from(&get_paths()?.task_dir_path);
-        let tar_gz_path = Path::new(&base_path).join(format!("{}.tar.gz", model_name));
-        let zip_path = Path::new(&base_path).join(format!("{}.zip", model_name));
-        let extracted_path = Path::new(&base_path).join(model_name);
+        validate_model_name(model_name)?;
+        let extractor = Self::for_model(model_name, base_path);
+        let extracted_path = extractor.model_dir();
+        let archive_path = &extractor.archive_path;
 
-        // Check if already extracted
+        // Check if already extracted. A directory without the completion marker is
+        // a leftover from an interrupted run and gets redone, as long as the
+        // archive is still around to redo it from.
         if extracted_path.is_dir() {
-            println!("✅ Model already extracted at: {:?}", extracted_path);
-            return Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                "Model already extracted",
-            ));
+            if extracted_path.join(COMPLETION_MARKER).exists() || archive_path.is_none() {
+                println!("✅ Model already extracted at: {:?}", extracted_path);
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "Model already extracted",
+                ));
+            }
+            println!(
+                "⚠️ Incomplete extraction found at {:?}, extracting again",
+                extracted_path
+            );
         }
 
-        let archive_path = if tar_gz_path.exists() {
-            tar_gz_path
-        } else if zip_path.exists() {
-            zip_path
-        } else {
-            return Err(io::Error::new(
+        extractor.archive()?;
+        Ok(extractor)
+    }
+
+    /// Creates an extractor without checking whether the model is already extracted.
+    ///
+    /// Unlike `new`, this never fails; use `ensure_extracted` to bring the model
+    /// directory up to date with whatever archive is present.
+    pub fn for_model(model_name: &str, base_path: PathBuf) -> Self {
+        // let base_path = PathBuf::from(&get_paths()?.task_dir_path);
+        let archive_path = ARCHIVE_EXTENSIONS
+            .iter()
+            .map(|ext| base_path.join(format!("{}.{}", model_name, ext)))
+            .find(|path| path.is_file());
+
+        Self {
+            model_name: model_name.to_string(),
+            archive_path,
+            output_folder: base_path,
+            limits: ExtractionLimits::default(),
+            retention: ArchiveRetention::default(),
+            rewrite_config_name: false,
+            hash_algorithm: HashAlgorithm::default(),
+            audit_log: None,
+            key_provider: None,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+        }
+    }
+
+    /// Downloads the model archive from an HTTP(S) URL into `base_path` and returns an extractor for it.
+    ///
+    /// The download resumes if a previous attempt was interrupted; see `from_source`.
+    pub async fn fetch(
+        url: &str,
+        model_name: &str,
+        base_path: PathBuf,
+        expected_sha256: &str,
+    ) -> io::Result<Self> {
+        Self::from_source(
+            &HttpSource::new(url),
+            model_name,
+            base_path,
+            expected_sha256,
+        )
+        .await
+    }
+
+    /// Fetches the model archive from `source` into `base_path` and returns an extractor for it.
+    ///
+    /// The archive is checked against `expected_sha256` before it gets its final
+    /// `<model_name>.<ext>` name, the extension coming from its magic bytes. A
+    /// download that fails midway is kept so the next attempt can resume it; one
+    /// with the wrong digest is discarded.
+    pub async fn from_source(
+        source: &dyn ModelSource,
+        model_name: &str,
+        base_path: PathBuf,
+        expected_sha256: &str,
+    ) -> io::Result<Self> {
+        validate_model_name(model_name)?;
+        tokio::fs::create_dir_all(&base_path).await?;
+        let download = base_path.join(format!(".{}.download", model_name));
+        println!(
+            "⏳ Fetching model '{}' from {}",
+            model_name,
+            source.describe()
+        );
+
+        let computed = source.fetch(&download).await?;
+        if computed != expected_sha256.to_lowercase() {
+            let _ = tokio::fs::remove_file(&download).await;
+            return Err(checksum_mismatch(&download, &computed, expected_sha256));
+        }
+        println!("✅ Archive verified: {}", computed);
+
+        let archive_path =
+            base_path.join(format!("{}.{}", model_name, archive_extension(&download)?));
+        tokio::fs::rename(&download, &archive_path).await?;
+
+        let mut extractor = Self::for_model(model_name, base_path);
+        extractor.archive_path = Some(archive_path);
+        Ok(extractor)
+    }
+
+    /// Replaces the default extraction limits
+    pub fn with_limits(mut self, limits: ExtractionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Rewrites the top-level `name:` in config.pbtxt to match the model name after extraction
+    pub fn with_config_name_rewrite(mut self, rewrite: bool) -> Self {
+        self.rewrite_config_name = rewrite;
+        self
+    }
+
+    /// Algorithm for the hash manifest written after extraction (SHA-256 by default)
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    /// Records extraction outcomes in `audit_log`
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Supplies keys for encrypted (`.oirenc`) archives, which cannot be extracted without one
+    pub fn with_key_provider(mut self, key_provider: Arc<dyn KeyProvider>) -> Self {
+        self.key_provider = Some(key_provider);
+        self
+    }
+
+    /// How long `ensure_extracted` waits for another process extracting the same model
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Replaces the default retention policy, which deletes the archive
+    pub fn with_retention(mut self, retention: ArchiveRetention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Directory the model is extracted to
+    pub fn model_dir(&self) -> PathBuf {
+        self.output_folder.join(&self.model_name)
+    }
+
+    /// Extracts the model unless an identical extraction is already in place.
+    ///
+    /// The archive's SHA-256 is recorded in the completion marker, so an archive
+    /// that changed since the last extraction replaces the model directory.
+    /// Processes sharing the output folder take turns through a `ModelLock`; one
+    /// that waited finds the model already extracted by the one before it.
+    pub fn ensure_extracted(&self) -> io::Result<ExtractionStatus> {
+        validate_model_name(&self.model_name)?;
+        let result = ModelLock::acquire(&self.output_folder, &self.model_name, self.lock_timeout)
+            .and_then(|_lock| self.extract_if_needed());
+        if let Some(audit_log) = &self.audit_log {
+            let details = match &result {
+                Ok(status) => json!({
+                    "status": format!("{:?}", status),
+                    "archive": self.archive_path.as_ref().map(|path| path.display().to_string()),
+                }),
+                Err(e) => json!({ "status": "Failed", "error": e.to_string() }),
+            };
+            audit_log.record_or_warn("extraction", &self.model_name, details);
+        }
+        result
+    }
+
+    fn extract_if_needed(&self) -> io::Result<ExtractionStatus> {
+        let model_dir = self.model_dir();
+        let complete = model_dir.join(COMPLETION_MARKER).is_file();
+
+        let archive_path = match &self.archive_path {
+            // Another process extracted the model and disposed of the archive while we waited
+            Some(path) if complete && !path.exists() => {
+                println!("✅ Model already extracted at: {:?}", model_dir);
+                return Ok(ExtractionStatus::AlreadyPresent);
+            }
+            Some(path) => path,
+            None if model_dir.is_dir() => {
+                println!("✅ Model already present at: {:?}", model_dir);
+                return Ok(ExtractionStatus::AlreadyPresent);
+            }
+            None => return Err(archive_not_found()),
+        };
+
+        if !complete {
+            self.extract_locked()?;
+            return Ok(ExtractionStatus::Extracted);
+        }
+
+        let digest = sha256_file(archive_path)?;
+        if recorded_archive_digest(&model_dir).as_deref() == Some(digest.as_str()) {
+            println!(
+                "✅ Model already extracted from this archive at: {:?}",
+                model_dir
+            );
+            self.retain_archive(archive_path, &digest)?;
+            return Ok(ExtractionStatus::AlreadyPresent);
+        }
+
+        println!(
+            "🔄 Archive changed since last extraction, replacing {:?}",
+            model_dir
+        );
+        self.extract_locked()?;
+        Ok(ExtractionStatus::Replaced)
+    }
+
+    /// Archives of this model in the retention cache, newest first
+    pub fn cached_archives(&self) -> io::Result<Vec<PathBuf>> {
+        let cache_dir = match self.retention.cache_dir() {
+            Some(cache_dir) if cache_dir.is_dir() => cache_dir,
+            _ => return Ok(Vec::new()),
+        };
+
+        let prefix = format!("{}-", self.model_name);
+        let mut cached = Vec::new();
+        for entry in std::fs::read_dir(cache_dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let is_cached_archive = file_name.strip_prefix(&prefix).is_some_and(|rest| {
+                rest.split_once('.').is_some_and(|(digest, ext)| {
+                    digest.len() == CACHE_DIGEST_LEN
+                        && digest.chars().all(|c| c.is_ascii_hexdigit())
+                        && ARCHIVE_EXTENSIONS.contains(&ext)
+                })
+            });
+            if is_cached_archive {
+                cached.push((entry.metadata()?.modified()?, entry.path()));
+            }
+        }
+        cached.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+        Ok(cached.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// Extracts the newest cached archive of this model, replacing the model directory if it differs
+    pub fn reextract_from_cache(&self) -> io::Result<ExtractionStatus> {
+        let newest = self.cached_archives()?.into_iter().next().ok_or_else(|| {
+            io::Error::new(
                 io::ErrorKind::NotFound,
-                "Model archive not found",
-            ));
+                format!("No cached archive for model '{}'", self.model_name),
+            )
+        })?;
+        println!("♻️ Re-extracting {:?} from cache", newest);
+
+        let extractor = Self {
+            model_name: self.model_name.clone(),
+            archive_path: Some(newest),
+            output_folder: self.output_folder.clone(),
+            limits: self.limits.clone(),
+            retention: ArchiveRetention::Keep,
+            rewrite_config_name: self.rewrite_config_name,
+            hash_algorithm: self.hash_algorithm,
+            audit_log: self.audit_log.clone(),
+            key_provider: self.key_provider.clone(),
+            lock_timeout: self.lock_timeout,
         };
+        extractor.ensure_extracted()
+    }
 
-        Ok(Self {
-            archive_path,
-            output_folder: PathBuf::from(base_path),
-        })
+    /// Applies the retention policy to an archive that has been extracted
+    fn retain_archive(&self, archive_path: &Path, digest: &str) -> io::Result<()> {
+        let cache_dir = match &self.retention {
+            ArchiveRetention::Delete | ArchiveRetention::KeepLastN { keep: 0, .. } => {
+                println!("🗑️ Deleting archive {:?}", archive_path);
+                return remove_file(archive_path);
+            }
+            ArchiveRetention::Keep => return Ok(()),
+            ArchiveRetention::MoveToCache(cache_dir)
+            | ArchiveRetention::KeepLastN { cache_dir, .. } => cache_dir,
+        };
+
+        std::fs::create_dir_all(cache_dir)?;
+        let cached = cache_dir.join(format!(
+            "{}-{}.{}",
+            self.model_name,
+            &digest[..CACHE_DIGEST_LEN],
+            archive_extension(archive_path)?
+        ));
+
+        println!("📦 Moving archive {:?} to {:?}", archive_path, cached);
+        if cached.exists() {
+            remove_file(archive_path)?;
+        } else if std::fs::rename(archive_path, &cached).is_err() {
+            // The cache may live on another filesystem
+            std::fs::copy(archive_path, &cached)?;
+            remove_file(archive_path)?;
+        }
+        // Order the cache by when archives were last extracted, not by when they were built
+        File::options()
+            .write(true)
+            .open(&cached)?
+            .set_modified(std::time::SystemTime::now())?;
+
+        if let ArchiveRetention::KeepLastN { keep, .. } = self.retention {
+            for stale in self.cached_archives()?.into_iter().skip(keep) {
+                println!("🗑️ Pruning cached archive {:?}", stale);
+                remove_file(stale)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn archive(&self) -> io::Result<&Path> {
+        self.archive_path.as_deref().ok_or_else(archive_not_found)
     }
 
+    /// Extracts the archive unconditionally, holding the model's `ModelLock` while it does
     pub fn extract_model(&self) -> io::Result<()> {
-        let extension = self
-            .archive_path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .unwrap_or("");
-
-        match extension {
-            "gz" => self.extract_tar_gz(),
-            "zip" => self.extract_zip(),
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Unsupported archive format",
-            )),
-        }?;
+        let _lock = ModelLock::acquire(&self.output_folder, &self.model_name, self.lock_timeout)?;
+        self.extract_locked()
+    }
+
+    /// Does the work of `extract_model` for callers already holding the lock
+    fn extract_locked(&self) -> io::Result<()> {
+        let archive_path = self.archive()?;
+        let format = if is_encrypted(archive_path)? {
+            None
+        } else {
+            Some(ArchiveFormat::detect(archive_path)?)
+        };
+        let archive_size = std::fs::metadata(archive_path)?.len();
+        let archive_digest = sha256_file(archive_path)?;
+        let mut budget = ExtractionBudget::new(&self.limits, archive_size);
+
+        // Extract next to the final location so the closing rename stays on one filesystem
+        let staging = self.output_folder.join(format!(
+            ".staging-{}-{}",
+            self.model_name,
+            std::process::id()
+        ));
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
+        std::fs::create_dir_all(&staging)?;
+
+        let result = match format {
+            None => self.extract_encrypted(archive_path, &staging, &mut budget),
+            Some(ArchiveFormat::Zip) => self.extract_zip(archive_path, &staging, &mut budget),
+            Some(format) => {
+                let archive_file = BufReader::new(File::open(archive_path)?);
+                self.extract_tar(archive_file, format, &staging, &mut budget)
+            }
+        }
+        .and_then(|_| self.commit_staging(&staging, &archive_digest));
+
+        if let Err(e) = result {
+            println!("🧹 Extraction aborted, removing partial output: {}", e);
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+        // When the archive had no root folder the staging directory itself was moved into place
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
 
-        // Delete archive after extraction
-        println!("🗑️ Deleting archive {:?}", self.archive_path);
-        remove_file(&self.archive_path)?;
+        self.retain_archive(archive_path, &archive_digest)?;
 
         // 🧠 Compute hash of model.onnx
         // let model_name = self.archive_path
@@ -87,31 +566,228 @@ impl ModelExtractor {
         Ok(())
     }
 
-    /// Extracts all files from the tar.gz archive to the specified output folder
-    fn extract_tar_gz(&self) -> io::Result<()> {
-        println!("🔍 Detected .tar.gz format. Extracting...");
-        let archive_file = File::open(&self.archive_path)?;
-        let decoder = GzDecoder::new(BufReader::new(archive_file));
+    /// Finds the directory inside `staging` that holds the model.
+    ///
+    /// Accepts `<model_name>/...`, a single top-level folder with any other name,
+    /// or a model laid out directly at the archive root.
+    fn locate_model_root(&self, staging: &Path) -> io::Result<PathBuf> {
+        let named = staging.join(&self.model_name);
+        if std::fs::symlink_metadata(&named).is_ok_and(|m| m.is_dir()) {
+            return Ok(named);
+        }
+
+        let mut top_level = Vec::new();
+        for entry in std::fs::read_dir(staging)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Debris added by archivers on macOS
+            if name == "__MACOSX" || name == ".DS_Store" {
+                continue;
+            }
+            top_level.push((entry.path(), entry.file_type()?.is_dir()));
+        }
+
+        if looks_like_model_dir(staging)? {
+            println!(
+                "📐 Archive has no root folder, placing its contents in '{}'",
+                self.model_name
+            );
+            return Ok(staging.to_path_buf());
+        }
+        if let [(root, true)] = top_level.as_slice() {
+            println!(
+                "📐 Archive root {:?} does not match model name, renaming to '{}'",
+                root.file_name().unwrap_or_default(),
+                self.model_name
+            );
+            return Ok(root.clone());
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Archive does not contain a '{}' directory or a recognizable model layout",
+                self.model_name
+            ),
+        ))
+    }
+
+    /// Validates the staged model directory, marks it complete and renames it into place
+    fn commit_staging(&self, staging: &Path, archive_digest: &str) -> io::Result<()> {
+        let staged_model = self.locate_model_root(staging)?;
+        verify_links(&staged_model, &staged_model)?;
+
+        // An archive may ship its own manifest; its content has to match it
+        let manifest_path = staged_model.join(MANIFEST_FILE);
+        let shipped_manifest = match std::fs::symlink_metadata(&manifest_path) {
+            Ok(_) => {
+                let manifest = Manifest::read_from(&manifest_path)?;
+                let report = manifest.verify(&staged_model)?;
+                if !report.is_clean() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Archive content does not match its manifest ({})", report),
+                    ));
+                }
+                println!("✅ Archive content matches its manifest");
+                true
+            }
+            Err(_) => false,
+        };
+
+        let config_path = staged_model.join("config.pbtxt");
+        let rewrite = self.rewrite_config_name && config_path.is_file();
+        if rewrite {
+            rewrite_config_name(&config_path, &self.model_name)?;
+        }
+        if !shipped_manifest || rewrite {
+            Manifest::for_directory(&staged_model, &self.model_name, self.hash_algorithm)?
+                .write_to(&manifest_path)?;
+            // A publisher signature cannot cover a manifest generated here
+            let signature_path = staged_model.join(SIGNATURE_FILE);
+            if std::fs::symlink_metadata(&signature_path).is_ok() {
+                println!("⚠️ Dropping manifest signature that no longer matches the model");
+                std::fs::remove_file(&signature_path)?;
+            }
+        }
+
+        let mut marker = File::create(staged_model.join(COMPLETION_MARKER))?;
+        writeln!(marker, "archive: {}", self.archive()?.display())?;
+        writeln!(marker, "sha256: {}", archive_digest)?;
+        marker.sync_all()?;
+
+        let extracted_path = self.model_dir();
+        if std::fs::symlink_metadata(&extracted_path).is_err() {
+            return std::fs::rename(&staged_model, &extracted_path);
+        }
+
+        // Move the previous model aside rather than deleting it first, so a crash
+        // in between leaves one of the two models in place
+        let replaced = self.output_folder.join(format!(
+            ".replaced-{}-{}",
+            self.model_name,
+            std::process::id()
+        ));
+        if std::fs::symlink_metadata(&replaced).is_ok() {
+            std::fs::remove_dir_all(&replaced)?;
+        }
+        std::fs::rename(&extracted_path, &replaced)?;
+        if let Err(e) = std::fs::rename(&staged_model, &extracted_path) {
+            let _ = std::fs::rename(&replaced, &extracted_path);
+            return Err(e);
+        }
+        println!("🧹 Removing previous model directory {:?}", extracted_path);
+        if let Err(e) = std::fs::remove_dir_all(&replaced) {
+            println!("⚠️ Failed to remove previous model {:?}: {}", replaced, e);
+        }
+        Ok(())
+    }
+
+    /// Extracts all files from a tar archive, decompressing it on the fly, to the specified output folder.
+    ///
+    /// Archives come from untrusted submitters, so every entry is checked before
+    /// anything is written: paths must stay inside the output folder, links must
+    /// stay inside the model directory, and device files or FIFOs are rejected.
+    fn extract_tar<R: Read>(
+        &self,
+        archive_file: R,
+        format: ArchiveFormat,
+        root: &Path,
+        budget: &mut ExtractionBudget,
+    ) -> io::Result<()> {
+        println!("🔍 Detected .{} format. Extracting...", format.extension());
+        let decoder: Box<dyn Read + '_> = match format {
+            ArchiveFormat::TarGz => Box::new(GzDecoder::new(archive_file)),
+            ArchiveFormat::TarXz => Box::new(XzDecoder::new(archive_file)),
+            ArchiveFormat::TarZst => Box::new(zstd::stream::read::Decoder::new(archive_file)?),
+            ArchiveFormat::TarBz2 => Box::new(BzDecoder::new(archive_file)),
+            ArchiveFormat::Tar => Box::new(archive_file),
+            ArchiveFormat::Zip => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Zip archives cannot be read as tar",
+                ))
+            }
+        };
         let mut archive = Archive::new(decoder);
 
         for entry_result in archive.entries()? {
             let mut entry = entry_result?;
-            let path = entry.path()?.to_path_buf();
-            let output_path = self.output_folder.join(&path);
+            let raw_path = entry.path()?.to_path_buf();
+            let entry_type = entry.header().entry_type();
 
-            if entry.header().entry_type().is_dir() {
-                println!("📂 Creating directory {:?}", output_path);
-                std::fs::create_dir_all(&output_path)?;
+            // Extension headers carry metadata only and are consumed by `tar` itself
+            if matches!(
+                entry_type,
+                EntryType::XGlobalHeader
+                    | EntryType::XHeader
+                    | EntryType::GNULongName
+                    | EntryType::GNULongLink
+            ) {
                 continue;
             }
 
-            if let Some(parent) = output_path.parent() {
-                std::fs::create_dir_all(parent)?;
-            }
+            let path = sanitize_entry_path(&raw_path)?;
+            let output_path = root.join(&path);
+            reject_symlinked_parents(root, &path)?;
+            budget.start_entry(&raw_path)?;
 
-            let mut out_file = File::create(&output_path)?;
-            copy(&mut entry, &mut out_file)?;
-            println!("✅ Extracted {:?} to {:?}", path, &self.output_folder);
+            match entry_type {
+                EntryType::Directory => {
+                    if output_path.is_symlink() {
+                        return Err(unsafe_entry(&raw_path, "directory replaces a link"));
+                    }
+                    println!("📂 Creating directory {:?}", output_path);
+                    std::fs::create_dir_all(&output_path)?;
+                }
+                EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                    prepare_output_file(&output_path)?;
+                    let declared_size = entry.header().size()?;
+                    let mut out_file = File::create(&output_path)?;
+                    budget.copy(&raw_path, declared_size, &mut entry, &mut out_file)?;
+                    println!("✅ Extracted {:?} to {:?}", path, &self.output_folder);
+                }
+                EntryType::Symlink => {
+                    let target = entry
+                        .link_name()?
+                        .ok_or_else(|| unsafe_entry(&raw_path, "symlink without a target"))?
+                        .to_path_buf();
+                    check_symlink_target(&path, &target)
+                        .map_err(|reason| unsafe_entry(&raw_path, reason))?;
+                    prepare_output_file(&output_path)?;
+                    create_symlink(&target, &output_path)?;
+                    println!("🔗 Linked {:?} -> {:?}", path, target);
+                }
+                EntryType::Link => {
+                    let raw_target = entry
+                        .link_name()?
+                        .ok_or_else(|| unsafe_entry(&raw_path, "hard link without a target"))?
+                        .to_path_buf();
+                    let target = sanitize_entry_path(&raw_target)?;
+                    reject_symlinked_parents(root, &target)?;
+                    let source = root.join(&target);
+                    let source_metadata = std::fs::symlink_metadata(&source)?;
+                    if !source_metadata.is_file() {
+                        return Err(unsafe_entry(&raw_path, "hard link to a non-regular file"));
+                    }
+                    // Copy rather than link so later entries can never write through it
+                    prepare_output_file(&output_path)?;
+                    let mut out_file = File::create(&output_path)?;
+                    budget.copy(
+                        &raw_path,
+                        source_metadata.len(),
+                        &mut File::open(&source)?,
+                        &mut out_file,
+                    )?;
+                    println!("✅ Extracted {:?} to {:?}", path, &self.output_folder);
+                }
+                _ => {
+                    return Err(unsafe_entry(
+                        &raw_path,
+                        "device files, FIFOs and other special entries are not allowed",
+                    ));
+                }
+            }
         }
         Ok(())
     }
@@ -169,16 +845,61 @@ impl ModelExtractor {
     //         Ok(())
     //     }
 
+    /// Decrypts an encrypted archive chunk by chunk and extracts the tar archive inside it.
+    ///
+    /// Plaintext only reaches the tar reader once its chunk has been
+    /// authenticated; the rest of the stream is drained afterwards so a
+    /// truncated or tampered tail still fails the extraction.
+    fn extract_encrypted(
+        &self,
+        archive_path: &Path,
+        root: &Path,
+        budget: &mut ExtractionBudget,
+    ) -> io::Result<()> {
+        let keys = self.key_provider.as_deref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Archive is encrypted but no key provider is configured",
+            )
+        })?;
+        println!("🔐 Decrypting archive {:?}", archive_path);
+        let mut reader = DecryptingReader::new(BufReader::new(File::open(archive_path)?), keys)?;
+
+        let mut header = Vec::with_capacity(512);
+        (&mut reader).take(512).read_to_end(&mut header)?;
+        let format = match ArchiveFormat::from_magic(&header) {
+            Some(ArchiveFormat::Zip) | None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Encrypted archives must contain a tar archive",
+                ))
+            }
+            Some(format) => format,
+        };
+
+        let mut plaintext = io::Cursor::new(header).chain(reader);
+        self.extract_tar(&mut plaintext, format, root, budget)?;
+        copy(&mut plaintext, &mut io::sink())?;
+        Ok(())
+    }
+
     /// Extracts all files from the .zip archive to the specified output folder
     #[allow(deprecated)]
-    fn extract_zip(&self) -> io::Result<()> {
+    fn extract_zip(
+        &self,
+        archive_path: &Path,
+        root: &Path,
+        budget: &mut ExtractionBudget,
+    ) -> io::Result<()> {
         // println!("🔍 Detected .zip format. Extracting...");
-        let archive_file = File::open(&self.archive_path)?;
+        let archive_file = File::open(archive_path)?;
         let mut archive = ZipArchive::new(archive_file)?;
 
         for i in 0..archive.len() {
             let mut file = archive.by_index(i)?;
-            let out_path = self.output_folder.join(file.sanitized_name());
+            let name = PathBuf::from(file.name());
+            budget.start_entry(&name)?;
+            let out_path = root.join(file.sanitized_name());
 
             if file.is_dir() {
                 // println!("📂 Creating directory {:?}", out_path);
@@ -187,8 +908,9 @@ impl ModelExtractor {
                 if let Some(parent) = out_path.parent() {
                     std::fs::create_dir_all(parent)?;
                 }
+                let declared_size = file.size();
                 let mut out_file = File::create(&out_path)?;
-                copy(&mut file, &mut out_file)?;
+                budget.copy(&name, declared_size, &mut file, &mut out_file)?;
                 //  println!("✅ Extracted {:?} to {:?}", file.name(), &self.output_folder);
             }
         }
@@ -278,3 +1000,1110 @@ impl ModelExtractor {
     //         std::process::exit(1);
     //     }
 }
+
+/// Checks that `model_name` names a single directory inside the repository.
+///
+/// Model names end up in paths that get extracted into, evicted and wiped, so
+/// an empty name, `.`, `..` or one with a path separator is refused.
+pub fn validate_model_name(model_name: &str) -> io::Result<()> {
+    let valid = !model_name.is_empty()
+        && model_name != "."
+        && model_name != ".."
+        && !model_name.contains(['/', '\\', '\0']);
+    if valid {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid model name {:?}", model_name),
+        ))
+    }
+}
+
+fn archive_not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "Model archive not found")
+}
+
+/// Suffix an archive is stored under: `oirenc` when encrypted, otherwise that of its format
+fn archive_extension(path: &Path) -> io::Result<&'static str> {
+    if is_encrypted(path)? {
+        Ok(ENCRYPTED_EXTENSION)
+    } else {
+        Ok(ArchiveFormat::detect(path)?.extension())
+    }
+}
+
+/// Streams a file through SHA-256 and returns the lowercase hex digest
+pub(crate) fn sha256_file(path: &Path) -> io::Result<String> {
+    hash_file(path, HashAlgorithm::Sha256)
+}
+
+/// Archive digest stored in the completion marker of `model_dir`, if any
+fn recorded_archive_digest(model_dir: &Path) -> Option<String> {
+    let marker = std::fs::read_to_string(model_dir.join(COMPLETION_MARKER)).ok()?;
+    marker
+        .lines()
+        .find_map(|line| line.strip_prefix("sha256: "))
+        .map(|digest| digest.trim().to_string())
+}
+
+/// Running totals checked against `ExtractionLimits` while entries are written
+struct ExtractionBudget<'a> {
+    limits: &'a ExtractionLimits,
+    ratio_budget: u64,
+    total_written: u64,
+    entries: usize,
+}
+
+impl<'a> ExtractionBudget<'a> {
+    fn new(limits: &'a ExtractionLimits, archive_size: u64) -> Self {
+        Self {
+            limits,
+            ratio_budget: archive_size.saturating_mul(limits.max_compression_ratio),
+            total_written: 0,
+            entries: 0,
+        }
+    }
+
+    fn start_entry(&mut self, path: &Path) -> io::Result<()> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(limit_exceeded(
+                path,
+                format!("more than {} entries", self.limits.max_entries),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Copies one entry, stopping as soon as any limit would be crossed.
+    ///
+    /// Headers can lie about sizes, so the declared size only allows an early
+    /// rejection; the bytes actually read are what count.
+    fn copy<R: Read, W: Write>(
+        &mut self,
+        path: &Path,
+        declared_size: u64,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> io::Result<u64> {
+        if declared_size > self.limits.max_file_size {
+            return Err(limit_exceeded(
+                path,
+                format!("file larger than {} bytes", self.limits.max_file_size),
+            ));
+        }
+
+        let total_allowance = self
+            .limits
+            .max_total_size
+            .saturating_sub(self.total_written);
+        let ratio_allowance = self.ratio_budget.saturating_sub(self.total_written);
+        let allowance = self
+            .limits
+            .max_file_size
+            .min(total_allowance)
+            .min(ratio_allowance);
+
+        let written = copy(&mut reader.take(allowance.saturating_add(1)), writer)?;
+        if written > allowance {
+            let reason = if written > self.limits.max_file_size {
+                format!("file larger than {} bytes", self.limits.max_file_size)
+            } else if written > total_allowance {
+                format!("more than {} bytes in total", self.limits.max_total_size)
+            } else {
+                format!(
+                    "compression ratio above {}:1",
+                    self.limits.max_compression_ratio
+                )
+            };
+            return Err(limit_exceeded(path, reason));
+        }
+
+        self.total_written += written;
+        Ok(written)
+    }
+}
+
+fn limit_exceeded(path: &Path, reason: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Archive limit exceeded at {:?}: {}", path, reason),
+    )
+}
+
+fn unsafe_entry(path: &Path, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Unsafe archive entry {:?}: {}", path, reason),
+    )
+}
+
+/// Turns an archive entry path into a plain relative path, rejecting absolute paths and `..`
+fn sanitize_entry_path(path: &Path) -> io::Result<PathBuf> {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => return Err(unsafe_entry(path, "path traversal")),
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_entry(path, "absolute path"))
+            }
+        }
+    }
+    if clean.as_os_str().is_empty() {
+        return Err(unsafe_entry(path, "empty path"));
+    }
+    Ok(clean)
+}
+
+/// Refuses to write below a symlink, which would let a link planted by an earlier entry redirect output
+fn reject_symlinked_parents(root: &Path, relative: &Path) -> io::Result<()> {
+    let mut current = root.to_path_buf();
+    if let Some(parent) = relative.parent() {
+        for component in parent.components() {
+            current.push(component);
+            if current.is_symlink() {
+                return Err(unsafe_entry(relative, "parent directory is a link"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a symlink at `link_path` (relative to some root) resolves inside that root.
+///
+/// Targets must be relative and may only climb (`..`) before descending, so the
+/// lexical resolution below matches what the filesystem will do.
+fn check_symlink_target(link_path: &Path, target: &Path) -> Result<(), &'static str> {
+    let mut depth = link_path.components().count().saturating_sub(1);
+    let mut descending = false;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => {
+                descending = true;
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir if descending => {
+                return Err("link target climbs after descending")
+            }
+            Component::ParentDir if depth == 0 => {
+                return Err("link target escapes the model directory")
+            }
+            Component::ParentDir => depth -= 1,
+            Component::RootDir | Component::Prefix(_) => return Err("absolute link target"),
+        }
+    }
+    Ok(())
+}
+
+/// Re-checks every symlink below `dir` against `root`, the directory that becomes the model directory
+fn verify_links(root: &Path, dir: &Path) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = std::fs::symlink_metadata(&path)?;
+        if metadata.is_symlink() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let target = std::fs::read_link(&path)?;
+            check_symlink_target(relative, &target)
+                .map_err(|reason| unsafe_entry(relative, reason))?;
+        } else if metadata.is_dir() {
+            verify_links(root, &path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `dir` looks like a Triton model directory: a config or a numeric version folder
+fn looks_like_model_dir(dir: &Path) -> io::Result<bool> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_dir = entry.file_type()?.is_dir();
+        if (name == "config.pbtxt" && !is_dir)
+            || (is_dir && !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()))
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Sets the top-level `name:` of a config.pbtxt to `model_name`, adding it if missing.
+///
+/// Nested `name:` fields (inputs, outputs, ...) are left alone by tracking bracket
+/// depth outside of strings and comments.
+fn rewrite_config_name(config_path: &Path, model_name: &str) -> io::Result<()> {
+    let original = std::fs::read_to_string(config_path)?;
+    let name_line = format!("name: \"{}\"", model_name);
+    let mut depth: i32 = 0;
+    let mut replaced = false;
+    let mut lines = Vec::new();
+
+    for line in original.lines() {
+        let trimmed = line.trim_start();
+        let is_top_level_name = depth == 0
+            && trimmed
+                .strip_prefix("name")
+                .is_some_and(|rest| rest.trim_start().starts_with(':'));
+        if is_top_level_name && !replaced {
+            lines.push(name_line.clone());
+            replaced = true;
+        } else {
+            lines.push(line.to_string());
+        }
+
+        let mut in_string = false;
+        let mut escaped = false;
+        for c in line.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '#' if !in_string => break,
+                '{' | '[' if !in_string => depth += 1,
+                '}' | ']' if !in_string => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    if !replaced {
+        lines.insert(0, name_line);
+    }
+    let mut rewritten = lines.join("\n");
+    rewritten.push('\n');
+    if rewritten != original {
+        println!(
+            "✏️ Setting model name in {:?} to '{}'",
+            config_path, model_name
+        );
+        std::fs::write(config_path, rewritten)?;
+    }
+    Ok(())
+}
+
+/// Creates the parent directories of `path` and removes a link already sitting at `path`
+fn prepare_output_file(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if path.is_symlink() {
+        remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, link: &Path) -> io::Result<()> {
+    Err(unsafe_entry(
+        link,
+        "symlinks are not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tar::{Builder, Header};
+
+    /// Builds a header without the validation `Header::set_path` performs, so hostile names can be crafted
+    fn raw_header(path: &str, entry_type: EntryType, size: u64) -> Header {
+        let mut header = Header::new_gnu();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(0o644);
+        header
+    }
+
+    fn link_header(path: &str, entry_type: EntryType, target: &str) -> Header {
+        let mut header = raw_header(path, entry_type, 0);
+        header.as_old_mut().linkname[..target.len()].copy_from_slice(target.as_bytes());
+        header
+    }
+
+    fn write_tar_gz(repo: &Path, model_name: &str, entries: Vec<(Header, &[u8])>) {
+        let file = File::create(repo.join(format!("{}.tar.gz", model_name))).unwrap();
+        let mut builder = Builder::new(GzEncoder::new(file, Compression::default()));
+        for (mut header, data) in entries {
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn extract(repo: &Path, model_name: &str) -> io::Result<()> {
+        ModelExtractor::new(model_name, repo.to_path_buf())?.extract_model()
+    }
+
+    fn setup() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        std::fs::create_dir(&repo).unwrap();
+        (dir, repo)
+    }
+
+    #[test]
+    fn rejects_parent_traversal() {
+        let (dir, repo) = setup();
+        let payload = b"owned";
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![(
+                raw_header("simple/../../evil.txt", EntryType::Regular, 5),
+                payload,
+            )],
+        );
+
+        let err = extract(&repo, "simple").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!dir.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let (dir, repo) = setup();
+        let target = dir.path().join("evil.txt");
+        let payload = b"owned";
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![(
+                raw_header(target.to_str().unwrap(), EntryType::Regular, 5),
+                payload,
+            )],
+        );
+
+        assert!(extract(&repo, "simple").is_err());
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn rejects_symlink_escaping_model_directory() {
+        let (_dir, repo) = setup();
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![(
+                link_header("simple/escape", EntryType::Symlink, "../../.."),
+                &[],
+            )],
+        );
+
+        assert!(extract(&repo, "simple").is_err());
+        assert!(!repo.join("simple/escape").exists());
+    }
+
+    #[test]
+    fn rejects_writing_through_symlink() {
+        let (dir, repo) = setup();
+        // `.` is inside the model directory, but `link/..` on disk would reach the repository root
+        let payload = b"owned";
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![
+                (link_header("simple/link", EntryType::Symlink, "."), &[]),
+                (
+                    raw_header("simple/link/evil.txt", EntryType::Regular, 5),
+                    payload,
+                ),
+            ],
+        );
+
+        assert!(extract(&repo, "simple").is_err());
+        assert!(!repo.join("simple/evil.txt").exists());
+        assert!(!dir.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn rejects_hard_link_outside_model_directory() {
+        let (_dir, repo) = setup();
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![(
+                link_header("simple/passwd", EntryType::Link, "/etc/passwd"),
+                &[],
+            )],
+        );
+
+        assert!(extract(&repo, "simple").is_err());
+        assert!(!repo.join("simple/passwd").exists());
+    }
+
+    #[test]
+    fn rejects_device_files() {
+        let (_dir, repo) = setup();
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![(raw_header("simple/tty", EntryType::Char, 0), &[])],
+        );
+
+        let err = extract(&repo, "simple").unwrap_err();
+        assert!(err.to_string().contains("special entries"));
+    }
+
+    #[test]
+    fn extracts_links_inside_model_directory() {
+        let (_dir, repo) = setup();
+        let model = b"onnx";
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![
+                (raw_header("simple/1/", EntryType::Directory, 0), &[]),
+                (
+                    raw_header("simple/1/model.onnx", EntryType::Regular, 4),
+                    model,
+                ),
+                (link_header("simple/2", EntryType::Symlink, "1"), &[]),
+                (
+                    link_header("simple/copy.onnx", EntryType::Link, "simple/1/model.onnx"),
+                    &[],
+                ),
+            ],
+        );
+
+        extract(&repo, "simple").unwrap();
+        assert_eq!(
+            std::fs::read(repo.join("simple/2/model.onnx")).unwrap(),
+            model
+        );
+        assert_eq!(std::fs::read(repo.join("simple/copy.onnx")).unwrap(), model);
+        assert!(!repo.join("simple.tar.gz").exists());
+    }
+
+    fn write_zip(repo: &Path, model_name: &str, files: &[(&str, &[u8])]) {
+        let file = File::create(repo.join(format!("{}.zip", model_name))).unwrap();
+        let mut writer = zip::ZipWriter::new(file);
+        for (name, data) in files {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn extract_with(repo: &Path, model_name: &str, limits: ExtractionLimits) -> io::Result<()> {
+        ModelExtractor::new(model_name, repo.to_path_buf())?
+            .with_limits(limits)
+            .extract_model()
+    }
+
+    #[test]
+    fn enforces_entry_count_and_cleans_up() {
+        let (_dir, repo) = setup();
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![
+                (raw_header("simple/a", EntryType::Regular, 1), b"a"),
+                (raw_header("simple/b", EntryType::Regular, 1), b"b"),
+                (raw_header("simple/c", EntryType::Regular, 1), b"c"),
+            ],
+        );
+        let limits = ExtractionLimits {
+            max_entries: 2,
+            ..ExtractionLimits::default()
+        };
+
+        let err = extract_with(&repo, "simple", limits).unwrap_err();
+        assert!(err.to_string().contains("entries"));
+        assert!(!repo.join("simple").exists());
+        assert!(repo.join("simple.tar.gz").exists());
+    }
+
+    #[test]
+    fn enforces_per_file_and_total_size() {
+        let (_dir, repo) = setup();
+        let data = vec![7u8; 1024];
+        write_zip(
+            &repo,
+            "simple",
+            &[("simple/a.bin", &data), ("simple/b.bin", &data)],
+        );
+
+        let per_file = ExtractionLimits {
+            max_file_size: 1000,
+            ..ExtractionLimits::default()
+        };
+        let err = extract_with(&repo, "simple", per_file).unwrap_err();
+        assert!(err.to_string().contains("file larger than"));
+        assert!(!repo.join("simple").exists());
+
+        let total = ExtractionLimits {
+            max_total_size: 1500,
+            ..ExtractionLimits::default()
+        };
+        let err = extract_with(&repo, "simple", total).unwrap_err();
+        assert!(err.to_string().contains("in total"));
+        assert!(!repo.join("simple").exists());
+    }
+
+    #[test]
+    fn enforces_compression_ratio() {
+        let (_dir, repo) = setup();
+        let zeros = vec![0u8; 4 * 1024 * 1024];
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![(
+                raw_header("simple/zeros.bin", EntryType::Regular, zeros.len() as u64),
+                &zeros,
+            )],
+        );
+
+        let err = extract(&repo, "simple").unwrap_err();
+        assert!(err.to_string().contains("compression ratio"));
+        assert!(!repo.join("simple").exists());
+    }
+
+    fn simple_tar_gz(repo: &Path) {
+        write_tar_gz(
+            repo,
+            "simple",
+            vec![
+                (
+                    raw_header("simple/config.pbtxt", EntryType::Regular, 4),
+                    b"name",
+                ),
+                (
+                    raw_header("simple/1/model.onnx", EntryType::Regular, 4),
+                    b"onnx",
+                ),
+            ],
+        );
+    }
+
+    /// Staging directories and replaced models left behind by extraction
+    fn staging_dirs(repo: &Path) -> usize {
+        std::fs::read_dir(repo)
+            .unwrap()
+            .filter(|e| {
+                let name = e.as_ref().unwrap().file_name();
+                let name = name.to_string_lossy();
+                name.starts_with(".staging") || name.starts_with(".replaced")
+            })
+            .count()
+    }
+
+    #[test]
+    fn marks_completed_extraction() {
+        let (_dir, repo) = setup();
+        simple_tar_gz(&repo);
+
+        extract(&repo, "simple").unwrap();
+        assert!(repo.join("simple").join(COMPLETION_MARKER).exists());
+        assert_eq!(staging_dirs(&repo), 0);
+
+        let err = ModelExtractor::new("simple", repo.clone()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn redoes_incomplete_extraction() {
+        let (_dir, repo) = setup();
+        std::fs::create_dir_all(repo.join("simple/1")).unwrap();
+        std::fs::write(repo.join("simple/1/model.onnx"), b"trun").unwrap();
+        std::fs::write(repo.join("simple/stale.txt"), b"stale").unwrap();
+        simple_tar_gz(&repo);
+
+        extract(&repo, "simple").unwrap();
+        assert_eq!(
+            std::fs::read(repo.join("simple/1/model.onnx")).unwrap(),
+            b"onnx"
+        );
+        assert!(!repo.join("simple/stale.txt").exists());
+        assert!(repo.join("simple").join(COMPLETION_MARKER).exists());
+        assert_eq!(staging_dirs(&repo), 0);
+    }
+
+    #[test]
+    fn keeps_unmarked_directory_without_archive() {
+        let (_dir, repo) = setup();
+        std::fs::create_dir_all(repo.join("simple/1")).unwrap();
+
+        let err = ModelExtractor::new("simple", repo.clone()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(repo.join("simple/1").exists());
+    }
+
+    #[test]
+    fn failed_extraction_leaves_no_partial_directory() {
+        let (_dir, repo) = setup();
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![
+                (
+                    raw_header("simple/config.pbtxt", EntryType::Regular, 4),
+                    b"name",
+                ),
+                (raw_header("simple/tty", EntryType::Char, 0), &[]),
+            ],
+        );
+
+        assert!(extract(&repo, "simple").is_err());
+        assert!(!repo.join("simple").exists());
+        assert_eq!(staging_dirs(&repo), 0);
+    }
+
+    #[test]
+    fn rejects_unrecognized_layout() {
+        let (_dir, repo) = setup();
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![
+                (raw_header("one/readme.txt", EntryType::Regular, 4), b"name"),
+                (raw_header("two/readme.txt", EntryType::Regular, 4), b"name"),
+            ],
+        );
+
+        let err = extract(&repo, "simple").unwrap_err();
+        assert!(err.to_string().contains("recognizable model layout"));
+        assert!(!repo.join("simple").exists());
+        assert!(!repo.join("one").exists());
+    }
+
+    #[test]
+    fn renames_mismatched_root_folder() {
+        let (_dir, repo) = setup();
+        let config = b"name: \"resnet\"\ninput [\n  {\n    name: \"INPUT0\"\n  }\n]\n";
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![
+                (
+                    raw_header(
+                        "resnet/config.pbtxt",
+                        EntryType::Regular,
+                        config.len() as u64,
+                    ),
+                    config,
+                ),
+                (
+                    raw_header("resnet/1/model.onnx", EntryType::Regular, 4),
+                    b"onnx",
+                ),
+            ],
+        );
+
+        ModelExtractor::new("simple", repo.clone())
+            .unwrap()
+            .with_config_name_rewrite(true)
+            .extract_model()
+            .unwrap();
+        assert!(repo.join("simple/1/model.onnx").exists());
+        assert!(!repo.join("resnet").exists());
+        assert_eq!(
+            std::fs::read_to_string(repo.join("simple/config.pbtxt")).unwrap(),
+            "name: \"simple\"\ninput [\n  {\n    name: \"INPUT0\"\n  }\n]\n"
+        );
+    }
+
+    #[test]
+    fn wraps_archive_without_root_folder() {
+        let (_dir, repo) = setup();
+        let config = b"platform: \"onnxruntime_onnx\"\n";
+        write_zip(
+            &repo,
+            "simple",
+            &[("config.pbtxt", config), ("1/model.onnx", b"onnx")],
+        );
+
+        ModelExtractor::new("simple", repo.clone())
+            .unwrap()
+            .with_config_name_rewrite(true)
+            .extract_model()
+            .unwrap();
+        assert!(repo.join("simple/1/model.onnx").exists());
+        assert!(repo.join("simple").join(COMPLETION_MARKER).exists());
+        assert_eq!(
+            std::fs::read_to_string(repo.join("simple/config.pbtxt")).unwrap(),
+            "name: \"simple\"\nplatform: \"onnxruntime_onnx\"\n"
+        );
+        assert_eq!(staging_dirs(&repo), 0);
+    }
+
+    #[test]
+    fn rejects_links_leaving_the_chosen_model_root() {
+        let (_dir, repo) = setup();
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![
+                (
+                    raw_header("other/secret.txt", EntryType::Regular, 4),
+                    b"keys",
+                ),
+                (
+                    link_header("simple/secret", EntryType::Symlink, "../other/secret.txt"),
+                    &[],
+                ),
+            ],
+        );
+
+        let err = extract(&repo, "simple").unwrap_err();
+        assert!(err.to_string().contains("escapes"));
+        assert!(!repo.join("simple").exists());
+    }
+
+    fn simple_tar() -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        for (path, data) in [
+            ("simple/config.pbtxt", b"name"),
+            ("simple/1/model.onnx", b"onnx"),
+        ] {
+            let mut header = raw_header(path, EntryType::Regular, 4);
+            header.set_cksum();
+            builder.append(&header, &data[..]).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn compress(format: ArchiveFormat, tar: &[u8]) -> Vec<u8> {
+        match format {
+            ArchiveFormat::Tar => tar.to_vec(),
+            ArchiveFormat::TarGz => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(tar).unwrap();
+                encoder.finish().unwrap()
+            }
+            ArchiveFormat::TarXz => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(tar).unwrap();
+                encoder.finish().unwrap()
+            }
+            ArchiveFormat::TarZst => zstd::stream::encode_all(tar, 0).unwrap(),
+            ArchiveFormat::TarBz2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(tar).unwrap();
+                encoder.finish().unwrap()
+            }
+            ArchiveFormat::Zip => panic!("not a tar format"),
+        }
+    }
+
+    #[test]
+    fn extracts_every_tar_flavour() {
+        let formats = [
+            (ArchiveFormat::Tar, "tar"),
+            (ArchiveFormat::TarGz, "tgz"),
+            (ArchiveFormat::TarXz, "tar.xz"),
+            (ArchiveFormat::TarZst, "tar.zst"),
+            (ArchiveFormat::TarBz2, "tar.bz2"),
+        ];
+        for (format, extension) in formats {
+            let (_dir, repo) = setup();
+            let archive = repo.join(format!("simple.{}", extension));
+            std::fs::write(&archive, compress(format, &simple_tar())).unwrap();
+            assert_eq!(ArchiveFormat::detect(&archive).unwrap(), format);
+
+            extract(&repo, "simple").unwrap();
+            assert_eq!(
+                std::fs::read(repo.join("simple/1/model.onnx")).unwrap(),
+                b"onnx"
+            );
+        }
+    }
+
+    #[test]
+    fn detects_mislabelled_archives() {
+        let (_dir, repo) = setup();
+        // An xz-compressed tar uploaded under a .zip name
+        std::fs::write(
+            repo.join("simple.zip"),
+            compress(ArchiveFormat::TarXz, &simple_tar()),
+        )
+        .unwrap();
+
+        extract(&repo, "simple").unwrap();
+        assert_eq!(
+            std::fs::read(repo.join("simple/config.pbtxt")).unwrap(),
+            b"name"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_format() {
+        let (_dir, repo) = setup();
+        std::fs::write(repo.join("simple.tar.gz"), b"definitely not an archive").unwrap();
+
+        let err = extract(&repo, "simple").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn ensure_extracted_reports_status() {
+        let (_dir, repo) = setup();
+        simple_tar_gz(&repo);
+        let status = ModelExtractor::for_model("simple", repo.clone())
+            .ensure_extracted()
+            .unwrap();
+        assert_eq!(status, ExtractionStatus::Extracted);
+
+        // Same archive dropped again: nothing to do
+        simple_tar_gz(&repo);
+        let status = ModelExtractor::for_model("simple", repo.clone())
+            .ensure_extracted()
+            .unwrap();
+        assert_eq!(status, ExtractionStatus::AlreadyPresent);
+
+        // No archive at all: the existing extraction stands
+        assert!(!repo.join("simple.tar.gz").exists());
+        let status = ModelExtractor::for_model("simple", repo.clone())
+            .ensure_extracted()
+            .unwrap();
+        assert_eq!(status, ExtractionStatus::AlreadyPresent);
+
+        // A different archive replaces the model directory
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![(
+                raw_header("simple/1/model.onnx", EntryType::Regular, 4),
+                b"new!",
+            )],
+        );
+        let status = ModelExtractor::for_model("simple", repo.clone())
+            .ensure_extracted()
+            .unwrap();
+        assert_eq!(status, ExtractionStatus::Replaced);
+        assert_eq!(
+            std::fs::read(repo.join("simple/1/model.onnx")).unwrap(),
+            b"new!"
+        );
+        assert!(!repo.join("simple/config.pbtxt").exists());
+    }
+
+    #[test]
+    fn ensure_extracted_without_model_or_archive_fails() {
+        let (_dir, repo) = setup();
+        let err = ModelExtractor::for_model("simple", repo)
+            .ensure_extracted()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn keeps_archive_when_asked() {
+        let (_dir, repo) = setup();
+        simple_tar_gz(&repo);
+
+        ModelExtractor::for_model("simple", repo.clone())
+            .with_retention(ArchiveRetention::Keep)
+            .ensure_extracted()
+            .unwrap();
+        assert!(repo.join("simple.tar.gz").exists());
+        assert!(repo.join("simple/1/model.onnx").exists());
+    }
+
+    #[test]
+    fn caches_archives_and_reextracts() {
+        let (dir, repo) = setup();
+        let cache = dir.path().join("cache");
+        let retention = ArchiveRetention::KeepLastN {
+            cache_dir: cache.clone(),
+            keep: 2,
+        };
+
+        for version in [b"ver1", b"ver2", b"ver3"] {
+            write_tar_gz(
+                &repo,
+                "simple",
+                vec![(
+                    raw_header("simple/1/model.onnx", EntryType::Regular, 4),
+                    version,
+                )],
+            );
+            ModelExtractor::for_model("simple", repo.clone())
+                .with_retention(retention.clone())
+                .ensure_extracted()
+                .unwrap();
+            assert!(!repo.join("simple.tar.gz").exists());
+        }
+
+        let extractor = ModelExtractor::for_model("simple", repo.clone()).with_retention(retention);
+        let cached = extractor.cached_archives().unwrap();
+        assert_eq!(cached.len(), 2);
+
+        std::fs::remove_dir_all(repo.join("simple")).unwrap();
+        let status = extractor.reextract_from_cache().unwrap();
+        assert_eq!(status, ExtractionStatus::Extracted);
+        assert_eq!(
+            std::fs::read(repo.join("simple/1/model.onnx")).unwrap(),
+            b"ver3"
+        );
+        assert!(cached[0].exists());
+    }
+
+    #[test]
+    fn keeping_no_cached_archives_deletes() {
+        let (dir, repo) = setup();
+        let cache = dir.path().join("cache");
+        simple_tar_gz(&repo);
+        let extractor = ModelExtractor::for_model("simple", repo.clone()).with_retention(
+            ArchiveRetention::KeepLastN {
+                cache_dir: cache.clone(),
+                keep: 0,
+            },
+        );
+
+        extractor.ensure_extracted().unwrap();
+        assert!(!repo.join("simple.tar.gz").exists());
+        assert!(!cache.exists());
+        assert!(extractor.cached_archives().unwrap().is_empty());
+    }
+
+    #[test]
+    fn writes_manifest_and_checks_shipped_one() {
+        let (_dir, repo) = setup();
+        simple_tar_gz(&repo);
+        extract(&repo, "simple").unwrap();
+        let model_dir = repo.join("simple");
+        let manifest = Manifest::read_from(&model_dir.join(MANIFEST_FILE)).unwrap();
+        assert_eq!(manifest.model_name, "simple");
+        assert_eq!(manifest.model_version, "1");
+        assert!(manifest.verify(&model_dir).unwrap().is_clean());
+
+        // A shipped manifest that disagrees with the archive content is refused
+        let mut forged = manifest.clone();
+        forged
+            .files
+            .insert("1/model.onnx".to_string(), "0".repeat(64));
+        let forged = forged.to_bytes().unwrap();
+        let (_dir, repo) = setup();
+        write_tar_gz(
+            &repo,
+            "simple",
+            vec![
+                (
+                    raw_header("simple/config.pbtxt", EntryType::Regular, 4),
+                    b"name",
+                ),
+                (
+                    raw_header("simple/1/model.onnx", EntryType::Regular, 4),
+                    b"onnx",
+                ),
+                (
+                    raw_header(
+                        &format!("simple/{}", MANIFEST_FILE),
+                        EntryType::Regular,
+                        forged.len() as u64,
+                    ),
+                    &forged,
+                ),
+            ],
+        );
+        let err = extract(&repo, "simple").unwrap_err();
+        assert!(err.to_string().contains("modified: 1/model.onnx"));
+        assert!(!repo.join("simple").exists());
+    }
+
+    #[test]
+    fn extracts_encrypted_archive_with_key() {
+        use crate::cleanup::wipe_dir;
+        use crate::encryption::{encrypt_archive, StaticKeyProvider};
+
+        let key = [5u8; 32];
+        let (_dir, repo) = setup();
+        let plain = repo.join("plain.tar.zst");
+        std::fs::write(&plain, compress(ArchiveFormat::TarZst, &simple_tar())).unwrap();
+        let sealed = repo.join("simple.oirenc");
+        encrypt_archive(&plain, &sealed, "tenant-a", &key).unwrap();
+        std::fs::remove_file(&plain).unwrap();
+
+        let keep = || {
+            ModelExtractor::for_model("simple", repo.clone()).with_retention(ArchiveRetention::Keep)
+        };
+        let err = keep().extract_model().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        let wrong = StaticKeyProvider::new().with_key("tenant-a", [6; 32]);
+        let err = keep()
+            .with_key_provider(Arc::new(wrong))
+            .extract_model()
+            .unwrap_err();
+        assert!(err.to_string().contains("authentication failed"), "{}", err);
+        assert!(!repo.join("simple").exists());
+        assert_eq!(staging_dirs(&repo), 0);
+
+        let keys = StaticKeyProvider::new().with_key("tenant-a", key);
+        keep()
+            .with_key_provider(Arc::new(keys))
+            .extract_model()
+            .unwrap();
+        let model_dir = repo.join("simple");
+        assert_eq!(
+            std::fs::read(model_dir.join("1/model.onnx")).unwrap(),
+            b"onnx"
+        );
+
+        wipe_dir(&model_dir).unwrap();
+        assert!(!model_dir.exists());
+    }
+
+    #[test]
+    fn concurrent_extractors_take_turns() {
+        let (_dir, repo) = setup();
+        simple_tar_gz(&repo);
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let extractor = ModelExtractor::for_model("simple", repo.clone());
+                std::thread::spawn(move || extractor.ensure_extracted())
+            })
+            .collect();
+        let mut statuses: Vec<_> = workers
+            .into_iter()
+            .map(|worker| format!("{:?}", worker.join().unwrap().unwrap()))
+            .collect();
+        statuses.sort();
+        assert_eq!(
+            statuses,
+            [
+                "AlreadyPresent",
+                "AlreadyPresent",
+                "AlreadyPresent",
+                "Extracted"
+            ]
+        );
+        assert_eq!(
+            std::fs::read(repo.join("simple/1/model.onnx")).unwrap(),
+            b"onnx"
+        );
+        assert!(!repo.join("simple.tar.gz").exists());
+        assert_eq!(staging_dirs(&repo), 0);
+
+        let _held = ModelLock::acquire(&repo, "simple", Duration::ZERO).unwrap();
+        let err = ModelExtractor::for_model("simple", repo.clone())
+            .with_lock_timeout(Duration::from_millis(200))
+            .ensure_extracted()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // Forcing an extraction waits for the lock too
+        simple_tar_gz(&repo);
+        let err = ModelExtractor::for_model("simple", repo.clone())
+            .with_lock_timeout(Duration::from_millis(200))
+            .extract_model()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
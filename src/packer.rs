@@ -0,0 +1,363 @@
+use crate::digest::{DirectoryDigest, BOOKKEEPING_FILES};
+use crate::hash::{hash_bytes, HashAlgorithm};
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::models::{sha256_file, ArchiveFormat};
+use crate::signing::{sign_manifest_bytes, SIGNATURE_FILE};
+use ed25519_dalek::SigningKey;
+use flate2::{Compression, GzBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tar::{Builder, EntryType, Header};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+/// Per-file SHA-256 digests of a packed model, keyed by path inside the archive
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackManifest {
+    pub model_name: String,
+    pub format: String,
+    pub archive_sha256: String,
+    /// `DirectoryDigest` root of the packed model directory
+    pub model_digest: String,
+    pub files: BTreeMap<String, String>,
+}
+
+impl PackManifest {
+    /// Writes the manifest as pretty-printed JSON
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, json)
+    }
+}
+
+/// Builds model archives that `ModelExtractor` can unpack.
+///
+/// Output is reproducible: entries are sorted, and timestamps, ownership and
+/// permissions are fixed, so the same model always yields the same archive hash.
+pub struct ModelPacker {
+    model_dir: PathBuf,
+    model_name: String,
+    signing_key: Option<SigningKey>,
+}
+
+/// A file or directory to pack, relative to the model directory
+struct PackEntry {
+    relative: String,
+    is_dir: bool,
+    /// Generated contents for files that do not exist on disk
+    contents: Option<Vec<u8>>,
+}
+
+impl ModelPacker {
+    pub fn new(model_dir: PathBuf) -> io::Result<Self> {
+        let model_name = model_dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Cannot derive a model name from {:?}", model_dir),
+                )
+            })?
+            .to_string();
+        Ok(Self {
+            model_dir,
+            model_name,
+            signing_key: None,
+        })
+    }
+
+    /// Ships a hash manifest signed with `key` inside the archive.
+    ///
+    /// The manifest's creation time is fixed at 0 so signed archives stay reproducible.
+    pub fn with_signing_key(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Checks the model directory has a config.pbtxt and at least one populated version directory
+    pub fn validate(&self) -> io::Result<()> {
+        self.collect_entries().map(|_| ())
+    }
+
+    /// Packs the model directory into `output` as `.tar.gz` or `.zip`
+    pub fn pack(&self, format: ArchiveFormat, output: &Path) -> io::Result<PackManifest> {
+        let mut entries = self.collect_entries()?;
+        let model_digest = DirectoryDigest::compute(&self.model_dir)?.root;
+        if let Some(key) = &self.signing_key {
+            let mut manifest =
+                Manifest::for_directory(&self.model_dir, &self.model_name, HashAlgorithm::Sha256)?;
+            manifest.created_at = 0;
+            let manifest_bytes = manifest.to_bytes()?;
+            let signature = sign_manifest_bytes(&manifest_bytes, key);
+            for (name, contents) in [(MANIFEST_FILE, manifest_bytes), (SIGNATURE_FILE, signature)] {
+                entries.push(PackEntry {
+                    relative: name.to_string(),
+                    is_dir: false,
+                    contents: Some(contents),
+                });
+            }
+            entries.sort_by(|a, b| a.relative.cmp(&b.relative));
+        }
+        println!("📦 Packing model '{}' into {:?}", self.model_name, output);
+
+        match format {
+            ArchiveFormat::TarGz => self.write_tar_gz(&entries, output)?,
+            ArchiveFormat::Zip => self.write_zip(&entries, output)?,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Packing to .{} is not supported", format.extension()),
+                ))
+            }
+        }
+
+        let mut files = BTreeMap::new();
+        for entry in entries.iter().filter(|entry| !entry.is_dir) {
+            let digest = match &entry.contents {
+                Some(contents) => hash_bytes(contents, HashAlgorithm::Sha256),
+                None => sha256_file(&self.model_dir.join(&entry.relative))?,
+            };
+            files.insert(self.archive_name(entry), digest);
+        }
+
+        Ok(PackManifest {
+            model_name: self.model_name.clone(),
+            format: format.extension().to_string(),
+            archive_sha256: sha256_file(output)?,
+            model_digest,
+            files,
+        })
+    }
+
+    fn archive_name(&self, entry: &PackEntry) -> String {
+        format!("{}/{}", self.model_name, entry.relative)
+    }
+
+    /// Walks the model directory into a sorted entry list, validating it on the way
+    fn collect_entries(&self) -> io::Result<Vec<PackEntry>> {
+        if !self.model_dir.join("config.pbtxt").is_file() {
+            return Err(invalid_model(&self.model_dir, "missing config.pbtxt"));
+        }
+
+        let mut entries = Vec::new();
+        collect_dir(&self.model_dir, "", &mut entries)?;
+        entries.sort_by(|a, b| a.relative.cmp(&b.relative));
+
+        let has_version = entries.iter().any(|entry| {
+            entry.relative.split_once('/').is_some_and(|(version, _)| {
+                version.chars().all(|c| c.is_ascii_digit()) && !version.is_empty()
+            }) && !entry.is_dir
+        });
+        if !has_version {
+            return Err(invalid_model(
+                &self.model_dir,
+                "no numeric version directory containing files",
+            ));
+        }
+        Ok(entries)
+    }
+
+    fn write_tar_gz(&self, entries: &[PackEntry], output: &Path) -> io::Result<()> {
+        // Fixed gzip mtime so the compressed stream is byte-for-byte reproducible
+        let encoder = GzBuilder::new()
+            .mtime(0)
+            .write(File::create(output)?, Compression::default());
+        let mut builder = Builder::new(encoder);
+
+        let mut root = fixed_header(EntryType::Directory, 0o755, 0);
+        builder.append_data(&mut root, format!("{}/", self.model_name), io::empty())?;
+
+        for entry in entries {
+            let name = self.archive_name(entry);
+            if entry.is_dir {
+                let mut header = fixed_header(EntryType::Directory, 0o755, 0);
+                builder.append_data(&mut header, format!("{}/", name), io::empty())?;
+            } else if let Some(contents) = &entry.contents {
+                let mut header = fixed_header(EntryType::Regular, 0o644, contents.len() as u64);
+                builder.append_data(&mut header, name, contents.as_slice())?;
+            } else {
+                let path = self.model_dir.join(&entry.relative);
+                let size = std::fs::metadata(&path)?.len();
+                let mut header = fixed_header(EntryType::Regular, 0o644, size);
+                builder.append_data(&mut header, name, File::open(&path)?)?;
+            }
+        }
+
+        builder.into_inner()?.finish()?.sync_all()
+    }
+
+    fn write_zip(&self, entries: &[PackEntry], output: &Path) -> io::Result<()> {
+        let mut writer = ZipWriter::new(File::create(output)?);
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(DateTime::default());
+
+        writer.add_directory(
+            format!("{}/", self.model_name),
+            options.unix_permissions(0o755),
+        )?;
+        for entry in entries {
+            let name = self.archive_name(entry);
+            if entry.is_dir {
+                writer.add_directory(format!("{}/", name), options.unix_permissions(0o755))?;
+            } else {
+                writer.start_file(name, options.unix_permissions(0o644))?;
+                match &entry.contents {
+                    Some(contents) => writer.write_all(contents)?,
+                    None => {
+                        io::copy(
+                            &mut File::open(self.model_dir.join(&entry.relative))?,
+                            &mut writer,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        writer.finish()?.flush()
+    }
+}
+
+fn fixed_header(entry_type: EntryType, mode: u32, size: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(mode);
+    header.set_size(size);
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+}
+
+fn invalid_model(model_dir: &Path, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid model directory {:?}: {}", model_dir, reason),
+    )
+}
+
+fn collect_dir(dir: &Path, prefix: &str, entries: &mut Vec<PackEntry>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| invalid_model(dir, &format!("non UTF-8 file name {:?}", name)))?;
+        // Markers, manifests and signatures from an earlier extraction are not model content
+        if prefix.is_empty() && BOOKKEEPING_FILES.contains(&name.as_str()) {
+            continue;
+        }
+
+        let relative = format!("{}{}", prefix, name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            entries.push(PackEntry {
+                relative: relative.clone(),
+                is_dir: true,
+                contents: None,
+            });
+            collect_dir(&entry.path(), &format!("{}/", relative), entries)?;
+        } else if file_type.is_file() {
+            entries.push(PackEntry {
+                relative,
+                is_dir: false,
+                contents: None,
+            });
+        } else {
+            // Links and special files would make the archive depend on the packing host
+            return Err(invalid_model(
+                dir,
+                &format!("'{}' is not a regular file or directory", relative),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ModelExtractor;
+
+    fn write_model(dir: &Path) -> PathBuf {
+        let model_dir = dir.join("simple");
+        std::fs::create_dir_all(model_dir.join("1")).unwrap();
+        std::fs::write(model_dir.join("config.pbtxt"), "name: \"simple\"\n").unwrap();
+        std::fs::write(model_dir.join("labels.txt"), "cat\ndog\n").unwrap();
+        std::fs::write(model_dir.join("1/model.onnx"), b"onnx").unwrap();
+        model_dir
+    }
+
+    #[test]
+    fn packs_reproducibly() {
+        for format in [ArchiveFormat::TarGz, ArchiveFormat::Zip] {
+            let dir = tempfile::tempdir().unwrap();
+            let model_dir = write_model(dir.path());
+            let packer = ModelPacker::new(model_dir.clone()).unwrap();
+
+            let first = packer.pack(format, &dir.path().join("a")).unwrap();
+            // Touching a file must not change the archive
+            File::options()
+                .write(true)
+                .open(model_dir.join("labels.txt"))
+                .unwrap()
+                .set_modified(std::time::SystemTime::now())
+                .unwrap();
+            let second = packer.pack(format, &dir.path().join("b")).unwrap();
+
+            assert_eq!(first, second);
+            assert_eq!(
+                std::fs::read(dir.path().join("a")).unwrap(),
+                std::fs::read(dir.path().join("b")).unwrap()
+            );
+            assert_eq!(
+                first.files.keys().collect::<Vec<_>>(),
+                [
+                    "simple/1/model.onnx",
+                    "simple/config.pbtxt",
+                    "simple/labels.txt"
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn packed_archive_extracts() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_dir = write_model(dir.path());
+        let repo = dir.path().join("repo");
+        std::fs::create_dir(&repo).unwrap();
+
+        let manifest = ModelPacker::new(model_dir)
+            .unwrap()
+            .pack(ArchiveFormat::TarGz, &repo.join("simple.tar.gz"))
+            .unwrap();
+        ModelExtractor::new("simple", repo.clone())
+            .unwrap()
+            .extract_model()
+            .unwrap();
+        assert_eq!(
+            DirectoryDigest::compute(&repo.join("simple")).unwrap().root,
+            manifest.model_digest
+        );
+        assert_eq!(
+            std::fs::read(repo.join("simple/1/model.onnx")).unwrap(),
+            b"onnx"
+        );
+    }
+
+    #[test]
+    fn rejects_model_without_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_dir = write_model(dir.path());
+        std::fs::remove_dir_all(model_dir.join("1")).unwrap();
+
+        let err = ModelPacker::new(model_dir).unwrap().validate().unwrap_err();
+        assert!(err.to_string().contains("version directory"));
+    }
+}
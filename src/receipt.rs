@@ -0,0 +1,303 @@
+use crate::client::TensorData;
+use crate::signing::TrustedKeys;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Prefix of the signed payload, so a receipt signature cannot be replayed as anything else
+const RECEIPT_DOMAIN: &str = "OIR_RECEIPT_V1";
+
+/// Node key and log file used to issue receipts for `TritonClient::run_inference`
+#[derive(Clone)]
+pub struct ReceiptConfig {
+    pub signing_key: SigningKey,
+    /// Receipts are appended here as JSON lines
+    pub log_path: PathBuf,
+}
+
+impl ReceiptConfig {
+    pub fn new(signing_key: SigningKey, log_path: PathBuf) -> Self {
+        Self {
+            signing_key,
+            log_path,
+        }
+    }
+}
+
+/// Signed statement that a given model turned given inputs into given outputs
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InferenceReceipt {
+    pub model_name: String,
+    /// `DirectoryDigest` root of the model directory that was loaded
+    pub model_digest: String,
+    pub inputs_sha256: String,
+    pub outputs_sha256: String,
+    pub started_at_ms: u64,
+    pub finished_at_ms: u64,
+    /// Triton server the inference ran on
+    pub endpoint: String,
+    /// Hex Ed25519 public key of the node that issued the receipt
+    pub node_key: String,
+    /// Hex Ed25519 signature over every other field
+    pub signature: String,
+}
+
+impl InferenceReceipt {
+    /// Fills in `node_key` and `signature` from `key`
+    pub fn signed(mut self, key: &SigningKey) -> Self {
+        self.node_key = hex::encode(key.verifying_key().as_bytes());
+        self.signature = hex::encode(key.sign(self.signed_payload().as_bytes()).to_bytes());
+        self
+    }
+
+    /// Checks the signature and that `inputs` and `outputs` are the ones the receipt covers.
+    ///
+    /// With `trusted` set, the issuing node key must also be one of those keys.
+    pub fn verify(
+        &self,
+        inputs: &HashMap<String, TensorData>,
+        outputs: &Value,
+        trusted: Option<&TrustedKeys>,
+    ) -> io::Result<()> {
+        let node_key = decode_fixed::<32>(&self.node_key)
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| invalid_receipt("malformed node key"))?;
+        let signature = decode_fixed::<64>(&self.signature)
+            .map(|bytes| Signature::from_bytes(&bytes))
+            .ok_or_else(|| invalid_receipt("malformed signature"))?;
+        node_key
+            .verify(self.signed_payload().as_bytes(), &signature)
+            .map_err(|_| invalid_receipt("signature does not match"))?;
+
+        if let Some(trusted) = trusted {
+            if !trusted.contains(&node_key) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("Receipt issued by untrusted node key {}", self.node_key),
+                ));
+            }
+        }
+        if hash_inputs(inputs) != self.inputs_sha256 {
+            return Err(invalid_receipt("inputs do not match"));
+        }
+        if hash_outputs(outputs) != self.outputs_sha256 {
+            return Err(invalid_receipt("outputs do not match"));
+        }
+        Ok(())
+    }
+
+    /// Appends the receipt to a JSON-lines log, creating it if needed
+    pub fn append_to(&self, log_path: &Path) -> io::Result<()> {
+        let line = serde_json::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path)?;
+        writeln!(log, "{}", line)?;
+        log.sync_all()
+    }
+
+    /// Reads every receipt from a log written by `append_to`
+    pub fn read_log(log_path: &Path) -> io::Result<Vec<Self>> {
+        std::fs::read_to_string(log_path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect()
+    }
+
+    fn signed_payload(&self) -> String {
+        format!(
+            "{}\nmodel_name={}\nmodel_digest={}\ninputs_sha256={}\noutputs_sha256={}\n\
+             started_at_ms={}\nfinished_at_ms={}\nendpoint={}\nnode_key={}\n",
+            RECEIPT_DOMAIN,
+            self.model_name,
+            self.model_digest,
+            self.inputs_sha256,
+            self.outputs_sha256,
+            self.started_at_ms,
+            self.finished_at_ms,
+            self.endpoint,
+            self.node_key
+        )
+    }
+}
+
+/// SHA-256 of the inputs in canonical form: names sorted, each with its datatype and data
+pub fn hash_inputs(inputs: &HashMap<String, TensorData>) -> String {
+    let canonical: serde_json::Map<String, Value> = inputs
+        .iter()
+        .map(|(name, tensor)| {
+            (
+                name.clone(),
+                json!({ "datatype": tensor.datatype(), "data": tensor.to_serializable() }),
+            )
+        })
+        .collect();
+    hash_canonical(&Value::Object(canonical))
+}
+
+/// SHA-256 of the inference response in canonical JSON form
+pub fn hash_outputs(outputs: &Value) -> String {
+    hash_canonical(outputs)
+}
+
+fn hash_canonical(value: &Value) -> String {
+    hex::encode(Sha256::digest(canonical_json(value).as_bytes()))
+}
+
+/// Compact JSON with object keys sorted, independent of map ordering
+pub(crate) fn canonical_json(value: &Value) -> String {
+    let mut canonical = String::new();
+    write_canonical(value, &mut canonical);
+    canonical
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut keys: Vec<_> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        leaf => out.push_str(&leaf.to_string()),
+    }
+}
+
+/// Milliseconds since the Unix epoch
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn decode_fixed<const N: usize>(hex_value: &str) -> Option<[u8; N]> {
+    hex::decode(hex_value).ok()?.try_into().ok()
+}
+
+fn invalid_receipt(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid inference receipt: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ClientOptions, TritonClient};
+    use crate::digest::DirectoryDigest;
+    use crate::test_support::{simple_inputs, simple_session, write_cassette};
+    use crate::transport::Transport;
+    use tokio_util::sync::CancellationToken;
+
+    #[test]
+    fn input_hash_ignores_map_order() {
+        let mut entries: Vec<_> = simple_inputs().into_iter().collect();
+        entries.reverse();
+        let reversed: HashMap<_, _> = entries.into_iter().collect();
+        assert_eq!(hash_inputs(&simple_inputs()), hash_inputs(&reversed));
+
+        let mut changed = simple_inputs();
+        changed.insert("INPUT1".to_string(), TensorData::I32(vec![1, 1, 1, 2]));
+        assert_ne!(hash_inputs(&simple_inputs()), hash_inputs(&changed));
+
+        // Same numbers under another datatype are different inputs
+        let mut retyped = simple_inputs();
+        retyped.insert("INPUT1".to_string(), TensorData::I64(vec![1, 1, 1, 1]));
+        assert_ne!(hash_inputs(&simple_inputs()), hash_inputs(&retyped));
+    }
+
+    #[tokio::test]
+    async fn run_inference_issues_verifiable_receipt() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = dir.path().join("simple.cassette");
+        write_cassette(&cassette, &simple_session());
+        let model_dir = dir.path().join("simple");
+        std::fs::create_dir_all(model_dir.join("1")).unwrap();
+        std::fs::write(model_dir.join("config.pbtxt"), "name: \"simple\"\n").unwrap();
+        std::fs::write(model_dir.join("1/model.onnx"), b"onnx").unwrap();
+
+        let node_key = SigningKey::from_bytes(&[3; 32]);
+        let log_path = dir.path().join("receipts.jsonl");
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            receipts: Some(ReceiptConfig::new(node_key.clone(), log_path.clone())),
+            ..ClientOptions::default()
+        };
+        let client = TritonClient::with_options(
+            "http://triton.invalid/v2",
+            "simple",
+            dir.path().to_path_buf(),
+            options,
+        )
+        .await
+        .unwrap();
+
+        let (outputs, receipt) = client
+            .run_inference_with_receipt(simple_inputs(), &CancellationToken::new())
+            .await
+            .unwrap();
+        let receipt = receipt.unwrap();
+        assert_eq!(InferenceReceipt::read_log(&log_path).unwrap().len(), 1);
+        assert_eq!(InferenceReceipt::read_log(&log_path).unwrap()[0], receipt);
+        assert_eq!(
+            receipt.model_digest,
+            DirectoryDigest::compute(&model_dir).unwrap().root
+        );
+        assert_eq!(receipt.endpoint, "http://triton.invalid/v2");
+        assert!(receipt.started_at_ms <= receipt.finished_at_ms);
+
+        let trusted = TrustedKeys::new().with_key(node_key.verifying_key());
+        receipt
+            .verify(&simple_inputs(), &outputs, Some(&trusted))
+            .unwrap();
+
+        let mut other_outputs = outputs.clone();
+        other_outputs["outputs"][0]["data"] = json!([0, 0, 0, 0]);
+        let err = receipt
+            .verify(&simple_inputs(), &other_outputs, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("outputs do not match"));
+
+        let mut forged = receipt.clone();
+        forged.model_digest = "0".repeat(64);
+        let err = forged.verify(&simple_inputs(), &outputs, None).unwrap_err();
+        assert!(err.to_string().contains("signature does not match"));
+
+        let err = receipt
+            .verify(&simple_inputs(), &outputs, Some(&TrustedKeys::new()))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::cleanup::wipe_dir;
+use crate::lock::{ModelLock, DEFAULT_LOCK_TIMEOUT};
+use crate::receipt::unix_millis;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Last-use times of the models in a repository, kept in its root
+pub const REPOSITORY_STATE_FILE: &str = ".oir_repository.json";
+
+/// Disk usage of one extracted model
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ModelUsage {
+    pub model_name: String,
+    pub size_bytes: u64,
+    /// Milliseconds since the Unix epoch; the directory's mtime until the model is first used
+    pub last_used_ms: u64,
+}
+
+/// Disk usage of the whole repository, least recently used model first
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RepositoryUsage {
+    pub quota_bytes: u64,
+    pub used_bytes: u64,
+    pub models: Vec<ModelUsage>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RepositoryState {
+    last_used_ms: BTreeMap<String, u64>,
+}
+
+/// Keeps the extracted models under `root` within a byte quota.
+///
+/// Every model directory counts against the quota. When it is exceeded, the
+/// least recently used models are wiped until usage fits again; models the
+/// server has loaded, and models another process is extracting, are skipped.
+#[derive(Clone, Debug)]
+pub struct RepositoryManager {
+    root: PathBuf,
+    quota_bytes: u64,
+}
+
+impl RepositoryManager {
+    pub fn new(root: PathBuf, quota_bytes: u64) -> Self {
+        Self { root, quota_bytes }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Marks `model_name` as used now
+    pub fn touch(&self, model_name: &str) -> io::Result<()> {
+        self.update_state(|state| {
+            state
+                .last_used_ms
+                .insert(model_name.to_string(), unix_millis());
+        })
+    }
+
+    /// Sizes and last-use times of every model directory
+    pub fn usage(&self) -> io::Result<RepositoryUsage> {
+        let state = self.read_state()?;
+        let mut models = Vec::new();
+        for entry in std::fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let model_name = entry.file_name().to_string_lossy().to_string();
+            if model_name.starts_with('.') || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let last_used_ms = match state.last_used_ms.get(&model_name) {
+                Some(&ms) => ms,
+                None => entry
+                    .metadata()?
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+            };
+            models.push(ModelUsage {
+                size_bytes: dir_size(&entry.path())?,
+                model_name,
+                last_used_ms,
+            });
+        }
+        models
+            .sort_by(|a, b| (a.last_used_ms, &a.model_name).cmp(&(b.last_used_ms, &b.model_name)));
+
+        Ok(RepositoryUsage {
+            quota_bytes: self.quota_bytes,
+            used_bytes: models.iter().map(|model| model.size_bytes).sum(),
+            models,
+        })
+    }
+
+    /// Evicts least recently used models not in `loaded` until usage fits the quota.
+    ///
+    /// Returns the evicted model names. Usage can stay above the quota when
+    /// every remaining model is loaded or busy; that is reported, not an error.
+    pub fn enforce_quota(&self, loaded: &HashSet<String>) -> io::Result<Vec<String>> {
+        let usage = self.usage()?;
+        let mut used_bytes = usage.used_bytes;
+        let mut evicted = Vec::new();
+
+        for model in usage.models {
+            if used_bytes <= self.quota_bytes {
+                break;
+            }
+            if loaded.contains(&model.model_name) {
+                continue;
+            }
+            // A model being extracted right now is in use too
+            let _lock = match ModelLock::acquire(&self.root, &model.model_name, Duration::ZERO) {
+                Ok(lock) => lock,
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(e) => return Err(e),
+            };
+            println!(
+                "🗑️ Evicting model '{}' ({} bytes) to stay within the repository quota",
+                model.model_name, model.size_bytes
+            );
+            wipe_dir(&self.root.join(&model.model_name))?;
+            used_bytes -= model.size_bytes;
+            evicted.push(model.model_name);
+        }
+
+        if !evicted.is_empty() {
+            self.update_state(|state| {
+                for model_name in &evicted {
+                    state.last_used_ms.remove(model_name);
+                }
+            })?;
+        }
+        if used_bytes > self.quota_bytes {
+            println!(
+                "⚠️ Repository uses {} bytes, over its {} byte quota, with nothing left to evict",
+                used_bytes, self.quota_bytes
+            );
+        }
+        Ok(evicted)
+    }
+
+    fn read_state(&self) -> io::Result<RepositoryState> {
+        match std::fs::read(self.root.join(REPOSITORY_STATE_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(RepositoryState::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read-modify-write of the state file under a lock shared by every process and manager
+    fn update_state(&self, update: impl FnOnce(&mut RepositoryState)) -> io::Result<()> {
+        // Models never start with '.', so this lock cannot clash with a model's
+        let _lock = ModelLock::acquire(&self.root, REPOSITORY_STATE_FILE, DEFAULT_LOCK_TIMEOUT)?;
+        let mut state = self.read_state()?;
+        update(&mut state);
+        self.write_state(&state)
+    }
+
+    fn write_state(&self, state: &RepositoryState) -> io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(state)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // Write then rename, so a reader never sees half a file
+        let temp = self
+            .root
+            .join(format!("{}.{}", REPOSITORY_STATE_FILE, std::process::id()));
+        std::fs::write(&temp, bytes)?;
+        std::fs::rename(&temp, self.root.join(REPOSITORY_STATE_FILE))
+    }
+}
+
+/// Total size of the regular files below `dir`; links are not followed
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            total += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            total += entry.metadata()?.len();
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ClientOptions, TritonClient};
+    use crate::test_support::{exchange, simple_inputs, simple_session, write_cassette};
+    use crate::transport::Transport;
+    use serde_json::json;
+
+    fn add_model(root: &Path, model_name: &str, size: usize) {
+        std::fs::create_dir_all(root.join(model_name).join("1")).unwrap();
+        std::fs::write(root.join(model_name).join("1/model.onnx"), vec![0u8; size]).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used_unloaded_models() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let manager = RepositoryManager::new(root.to_path_buf(), 250);
+        for model_name in ["a", "b", "c", "d"] {
+            add_model(root, model_name, 100);
+            manager.touch(model_name).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        manager.touch("a").unwrap();
+
+        let usage = manager.usage().unwrap();
+        assert_eq!(usage.used_bytes, 400);
+        let order: Vec<_> = usage.models.iter().map(|m| m.model_name.as_str()).collect();
+        assert_eq!(order, ["b", "c", "d", "a"]);
+
+        // "b" is loaded, so "c" and "d" go instead
+        let loaded = HashSet::from(["b".to_string()]);
+        assert_eq!(manager.enforce_quota(&loaded).unwrap(), ["c", "d"]);
+        assert!(root.join("a").is_dir() && root.join("b").is_dir());
+        assert!(!root.join("c").exists() && !root.join("d").exists());
+        assert_eq!(manager.usage().unwrap().used_bytes, 200);
+
+        // Nothing evictable left: the quota is reported as exceeded, not enforced
+        let small = RepositoryManager::new(root.to_path_buf(), 50);
+        let _busy = ModelLock::acquire(root, "a", Duration::ZERO).unwrap();
+        assert!(small.enforce_quota(&loaded).unwrap().is_empty());
+        assert!(root.join("a").is_dir());
+    }
+
+    #[test]
+    fn concurrent_managers_keep_every_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        let workers: Vec<_> = (0..4)
+            .map(|worker| {
+                // Separate managers, as separate processes would have
+                let manager = RepositoryManager::new(root.clone(), 0);
+                std::thread::spawn(move || {
+                    for i in 0..10 {
+                        manager.touch(&format!("model-{}-{}", worker, i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let state = RepositoryManager::new(root, 0).read_state().unwrap();
+        assert_eq!(state.last_used_ms.len(), 40);
+    }
+
+    #[tokio::test]
+    async fn client_load_enforces_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("repo");
+        let manager = RepositoryManager::new(root.clone(), 250);
+        for model_name in ["warming", "stale", "serving", "simple"] {
+            add_model(&root, model_name, 100);
+            manager.touch(model_name).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        std::fs::write(root.join("simple/config.pbtxt"), "name: \"simple\"\n").unwrap();
+        manager.touch("stale").unwrap();
+
+        let mut session = simple_session();
+        session.truncate(3);
+        session.push(exchange(
+            "POST",
+            "/repository/index",
+            Some(json!({})),
+            json!([
+                { "name": "serving", "version": "1", "state": "READY" },
+                { "name": "stale", "version": "1", "state": "UNAVAILABLE" },
+                { "name": "warming", "version": "1", "state": "LOADING" }
+            ]),
+        ));
+        session.extend_from_slice(&simple_session()[3..6]);
+        let cassette = dir.path().join("simple.cassette");
+        write_cassette(&cassette, &session);
+
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            repository: Some(manager.clone()),
+            ..ClientOptions::default()
+        };
+        let client =
+            TritonClient::with_options("http://triton.invalid/v2", "simple", root.clone(), options)
+                .await
+                .unwrap();
+
+        // "simple" became the most recent model, so the unloaded "stale" goes;
+        // "warming" is older but still loading
+        assert!(!root.join("stale").exists());
+        let usage = manager.usage().unwrap();
+        let order: Vec<_> = usage.models.iter().map(|m| m.model_name.as_str()).collect();
+        assert_eq!(order, ["warming", "serving", "simple"]);
+
+        // Inferences count as use too
+        let loaded_at = usage.models[2].last_used_ms;
+        std::thread::sleep(Duration::from_millis(5));
+        client.infer_with_receipt(simple_inputs()).await.unwrap();
+        assert!(manager.usage().unwrap().models[2].last_used_ms > loaded_at);
+    }
+}
@@ -0,0 +1,320 @@
+//! Ed25519 signatures over hash manifests.
+//!
+//! A publisher signs the exact bytes of `.oir_manifest` and ships the result as
+//! `.oir_manifest.sig` next to it. The signature file is fixed-size:
+//!
+//! ```text
+//! magic        14 bytes   "OIR_SIGNATURE\0"
+//! version      u16        1 (little-endian)
+//! public_key   32 bytes   Ed25519 key of the signer
+//! signature    64 bytes   Ed25519 signature of the manifest bytes
+//! ```
+
+use crate::manifest::MANIFEST_FILE;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::io;
+use std::path::Path;
+
+/// File name of the manifest signature inside the model directory
+pub const SIGNATURE_FILE: &str = ".oir_manifest.sig";
+
+const MAGIC: &[u8; 14] = b"OIR_SIGNATURE\0";
+const FORMAT_VERSION: u16 = 1;
+const SIGNATURE_FILE_LEN: usize = MAGIC.len() + 2 + 32 + 64;
+
+/// Publisher keys whose manifest signatures are accepted
+#[derive(Clone, Debug, Default)]
+pub struct TrustedKeys {
+    keys: Vec<VerifyingKey>,
+}
+
+impl TrustedKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key(mut self, key: VerifyingKey) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    /// Reads hex-encoded public keys, one per line; blank lines and `#` comments are ignored
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut trusted = Self::new();
+        for (number, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let key = parse_public_key(line).map_err(|reason| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{:?} line {}: {}", path, number + 1, reason),
+                )
+            })?;
+            trusted.keys.push(key);
+        }
+        Ok(trusted)
+    }
+
+    pub fn contains(&self, key: &VerifyingKey) -> bool {
+        self.keys.contains(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+fn parse_public_key(hex_key: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = hex::decode(hex_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| "expected 32 hex-encoded bytes".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string())
+}
+
+/// Signs `manifest_bytes` and returns the contents of a signature file
+pub fn sign_manifest_bytes(manifest_bytes: &[u8], key: &SigningKey) -> Vec<u8> {
+    let signature = key.sign(manifest_bytes);
+    let mut out = Vec::with_capacity(SIGNATURE_FILE_LEN);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(key.verifying_key().as_bytes());
+    out.extend_from_slice(&signature.to_bytes());
+    out
+}
+
+/// Signs the manifest of `model_dir` in place, writing the signature file next to it
+pub fn sign_model_dir(model_dir: &Path, key: &SigningKey) -> io::Result<()> {
+    let manifest_bytes = std::fs::read(model_dir.join(MANIFEST_FILE))?;
+    std::fs::write(
+        model_dir.join(SIGNATURE_FILE),
+        sign_manifest_bytes(&manifest_bytes, key),
+    )
+}
+
+/// Checks that the manifest of `model_dir` is signed by one of `trusted` and returns the signer.
+///
+/// Only the signature is checked here; whether the files match the manifest is
+/// up to `Manifest::verify`.
+pub fn verify_model_signature(model_dir: &Path, trusted: &TrustedKeys) -> io::Result<VerifyingKey> {
+    let manifest_bytes = std::fs::read(model_dir.join(MANIFEST_FILE))?;
+    verify_manifest_signature(model_dir, &manifest_bytes, trusted)
+}
+
+/// Checks the signature in `model_dir` over manifest bytes the caller already read.
+///
+/// Callers that go on to enforce the manifest parse these same bytes, so the
+/// file cannot be swapped between checking the signature and using it.
+pub fn verify_manifest_signature(
+    model_dir: &Path,
+    manifest_bytes: &[u8],
+    trusted: &TrustedKeys,
+) -> io::Result<VerifyingKey> {
+    let signature_path = model_dir.join(SIGNATURE_FILE);
+    let signature_file = match std::fs::read(&signature_path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Manifest signature {:?} is missing", signature_path),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+
+    let (public_key, signature) = parse_signature_file(&signature_file)?;
+    if !trusted.contains(&public_key) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "Manifest is signed by untrusted key {}",
+                hex::encode(public_key.as_bytes())
+            ),
+        ));
+    }
+    public_key
+        .verify(manifest_bytes, &signature)
+        .map_err(|_| invalid_signature("signature does not match the manifest"))?;
+    Ok(public_key)
+}
+
+fn parse_signature_file(bytes: &[u8]) -> io::Result<(VerifyingKey, Signature)> {
+    if bytes.len() != SIGNATURE_FILE_LEN || !bytes.starts_with(MAGIC) {
+        return Err(invalid_signature("malformed signature file"));
+    }
+    let rest = &bytes[MAGIC.len()..];
+    let version = u16::from_le_bytes([rest[0], rest[1]]);
+    if version != FORMAT_VERSION {
+        return Err(invalid_signature(&format!(
+            "unsupported signature version {}",
+            version
+        )));
+    }
+    let key_bytes: [u8; 32] = rest[2..34].try_into().expect("length checked above");
+    let signature_bytes: [u8; 64] = rest[34..].try_into().expect("length checked above");
+    let public_key =
+        VerifyingKey::from_bytes(&key_bytes).map_err(|_| invalid_signature("bad public key"))?;
+    Ok((public_key, Signature::from_bytes(&signature_bytes)))
+}
+
+fn invalid_signature(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid manifest signature: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ClientOptions, TritonClient};
+    use crate::hash::HashAlgorithm;
+    use crate::manifest::Manifest;
+    use crate::models::ArchiveFormat;
+    use crate::packer::ModelPacker;
+    use crate::transport::Transport;
+
+    fn signed_model(dir: &Path, key: &SigningKey) {
+        std::fs::create_dir_all(dir.join("1")).unwrap();
+        std::fs::write(dir.join("config.pbtxt"), "name: \"simple\"\n").unwrap();
+        std::fs::write(dir.join("1/model.onnx"), b"onnx").unwrap();
+        Manifest::for_directory(dir, "simple", HashAlgorithm::Sha256)
+            .unwrap()
+            .write_to(&dir.join(MANIFEST_FILE))
+            .unwrap();
+        sign_model_dir(dir, key).unwrap();
+    }
+
+    #[test]
+    fn accepts_only_trusted_valid_signatures() {
+        let dir = tempfile::tempdir().unwrap();
+        let publisher = SigningKey::from_bytes(&[7; 32]);
+        let stranger = SigningKey::from_bytes(&[9; 32]);
+        signed_model(dir.path(), &publisher);
+        let trusted = TrustedKeys::new().with_key(publisher.verifying_key());
+
+        assert_eq!(
+            verify_model_signature(dir.path(), &trusted).unwrap(),
+            publisher.verifying_key()
+        );
+
+        let err = verify_model_signature(dir.path(), &TrustedKeys::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        // A manifest edited after signing no longer verifies
+        let manifest_path = dir.path().join(MANIFEST_FILE);
+        let mut manifest = Manifest::read_from(&manifest_path).unwrap();
+        manifest.created_at += 1;
+        manifest.write_to(&manifest_path).unwrap();
+        let err = verify_model_signature(dir.path(), &trusted).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Re-signing with another key does not help unless that key is trusted
+        sign_model_dir(dir.path(), &stranger).unwrap();
+        let err = verify_model_signature(dir.path(), &trusted).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        std::fs::remove_file(dir.path().join(SIGNATURE_FILE)).unwrap();
+        let err = verify_model_signature(dir.path(), &trusted).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn loads_trusted_keys_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let publisher = SigningKey::from_bytes(&[7; 32]);
+        let path = dir.path().join("trusted_keys");
+        std::fs::write(
+            &path,
+            format!(
+                "# release key\n\n{}\n",
+                hex::encode(publisher.verifying_key().as_bytes())
+            ),
+        )
+        .unwrap();
+
+        let trusted = TrustedKeys::load(&path).unwrap();
+        assert!(trusted.contains(&publisher.verifying_key()));
+
+        std::fs::write(&path, "not-a-key\n").unwrap();
+        let err = TrustedKeys::load(&path).unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+    }
+
+    async fn load_with_keys(repo: &Path, trusted_keys: TrustedKeys) -> String {
+        // An empty cassette: any request made after verification fails as exhausted
+        let cassette = repo.join("empty.cassette");
+        std::fs::write(&cassette, "").unwrap();
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            trusted_keys: Some(trusted_keys),
+            ..ClientOptions::default()
+        };
+        TritonClient::with_options(
+            "http://triton.invalid/v2",
+            "simple",
+            repo.to_path_buf(),
+            options,
+        )
+        .await
+        .err()
+        .unwrap()
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn load_path_requires_trusted_signature() {
+        let publisher = SigningKey::from_bytes(&[7; 32]);
+        let trusted = TrustedKeys::new().with_key(publisher.verifying_key());
+
+        for signing_key in [Some(publisher.clone()), None] {
+            let dir = tempfile::tempdir().unwrap();
+            let source = dir.path().join("source/simple");
+            signed_model(&source, &publisher);
+            let repo = dir.path().join("repo");
+            std::fs::create_dir(&repo).unwrap();
+
+            let mut packer = ModelPacker::new(source).unwrap();
+            if let Some(key) = signing_key.clone() {
+                packer = packer.with_signing_key(key);
+            }
+            packer
+                .pack(ArchiveFormat::TarGz, &repo.join("simple.tar.gz"))
+                .unwrap();
+
+            let err = load_with_keys(&repo, trusted.clone()).await;
+            if signing_key.is_some() {
+                // Verification passed and the client went on to talk to the server
+                assert!(err.contains("Cassette exhausted"), "{}", err);
+            } else {
+                assert!(err.contains("signature"), "{}", err);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_signed_manifest_for_another_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let publisher = SigningKey::from_bytes(&[7; 32]);
+        // Files and a valid signature for "simple", placed where "other" is loaded from
+        signed_model(&dir.path().join("other"), &publisher);
+        let trusted = TrustedKeys::new().with_key(publisher.verifying_key());
+
+        let options = ClientOptions {
+            trusted_keys: Some(trusted),
+            ..ClientOptions::default()
+        };
+        let err = TritonClient::with_options(
+            "http://triton.invalid/v2",
+            "other",
+            dir.path().to_path_buf(),
+            options,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("'simple'"), "{}", err);
+    }
+}
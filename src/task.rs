@@ -0,0 +1,582 @@
+//! Declarative inference tasks.
+//!
+//! A task file describes one job end to end; `run_task` carries it out through
+//! every stage and reports how each one went:
+//!
+//! ```json
+//! {
+//!   "task_id": "job-42",
+//!   "triton_url": "http://localhost:8000/v2",
+//!   "model_name": "densenet_onnx",
+//!   "archive": "https://example.com/densenet_onnx.tar.gz",
+//!   "expected_sha256": "<hex digest of the archive>",
+//!   "repository": "/var/lib/cyborg/miner/current_task",
+//!   "inputs": "inputs.jsonl",
+//!   "outputs": "outputs.jsonl",
+//!   "timeout_secs": 600,
+//!   "request_timeout_secs": 120
+//! }
+//! ```
+//!
+//! `archive` is an `http(s)://` URL or a local path. `inputs` holds one request
+//! per line, in the format `TritonClient::run` accepts; `outputs` gets one line
+//! per request, either the server's response or `{"error": ...}`. The optional
+//! `result` names the file the `TaskResult` JSON goes to. Relative paths are
+//! taken from the task file's directory.
+
+use crate::cleanup::wipe_dir;
+use crate::client::{ClientOptions, TensorData, TritonClient};
+use crate::models::{validate_model_name, ExtractionStatus, ModelExtractor};
+use crate::receipt::unix_millis;
+use crate::source::{HttpSource, LocalSource, ModelSource};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+type TaskError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Contents of a task file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskSpec {
+    pub task_id: String,
+    pub triton_url: String,
+    pub model_name: String,
+    /// `http(s)://` URL or local path of the model archive
+    pub archive: String,
+    pub expected_sha256: String,
+    /// Model repository the archive is extracted into
+    pub repository: PathBuf,
+    /// JSON-lines file of inference requests
+    pub inputs: PathBuf,
+    /// JSON-lines file the responses are written to
+    pub outputs: PathBuf,
+    /// File the `TaskResult` is written to; `<task file>.result.json` when loaded without one
+    #[serde(default)]
+    pub result: Option<PathBuf>,
+    /// Limit for everything up to and including writing the outputs
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Limit for each request to the server
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+    /// Leave the extracted model in the repository instead of wiping it
+    #[serde(default)]
+    pub keep_model: bool,
+}
+
+impl TaskSpec {
+    /// Reads a task file; relative paths in it, including a local `archive`, are taken from its directory
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut spec: TaskSpec = serde_json::from_slice(&std::fs::read(path)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid task file {:?}: {}", path, e),
+            )
+        })?;
+        validate_model_name(&spec.model_name)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        if !is_url(&spec.archive) {
+            spec.archive = base.join(&spec.archive).to_string_lossy().into_owned();
+        }
+        spec.repository = base.join(&spec.repository);
+        spec.inputs = base.join(&spec.inputs);
+        spec.outputs = base.join(&spec.outputs);
+        spec.result = Some(match &spec.result {
+            Some(result) => base.join(result),
+            None => path.with_extension("result.json"),
+        });
+        Ok(spec)
+    }
+}
+
+/// Timing and outcome of one stage of a task
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageResult {
+    pub stage: String,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Machine-readable outcome of `run_task`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub task_id: String,
+    pub model_name: String,
+    pub success: bool,
+    pub started_at_ms: u64,
+    pub duration_ms: u64,
+    /// Stages in the order they ran; a failed stage ends the task early
+    pub stages: Vec<StageResult>,
+    pub inferences: usize,
+    pub failed_inferences: usize,
+    /// First error of the task, if any
+    pub error: Option<String>,
+}
+
+impl TaskResult {
+    /// Writes the result as pretty-printed JSON
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, json)
+    }
+}
+
+/// Runs a task: fetch, extract, verify, load, infer, write outputs, unload and clean up.
+///
+/// Unloading and cleanup run even when an earlier stage failed or the task
+/// timed out, so a failed task does not leave its model behind.
+pub async fn run_task(spec: &TaskSpec, mut options: ClientOptions) -> TaskResult {
+    let started_at_ms = unix_millis();
+    let started = Instant::now();
+    println!("📋 Running task '{}'", spec.task_id);
+
+    // The model name becomes a path that cleanup wipes, so nothing runs with a bad one
+    if let Err(e) = validate_model_name(&spec.model_name) {
+        println!("❌ Task '{}' failed: {}", spec.task_id, e);
+        return TaskResult {
+            task_id: spec.task_id.clone(),
+            model_name: spec.model_name.clone(),
+            success: false,
+            started_at_ms,
+            duration_ms: started.elapsed().as_millis() as u64,
+            stages: Vec::new(),
+            inferences: 0,
+            failed_inferences: 0,
+            error: Some(e.to_string()),
+        };
+    }
+    if let Some(secs) = spec.request_timeout_secs {
+        options.transport = options
+            .transport
+            .with_request_timeout(Duration::from_secs(secs));
+    }
+    let mut run = TaskRun::default();
+    let completed = match spec.timeout_secs {
+        Some(secs) => {
+            let limit = Duration::from_secs(secs);
+            tokio::time::timeout(limit, run.main_stages(spec, &options))
+                .await
+                .is_ok()
+        }
+        None => {
+            run.main_stages(spec, &options).await;
+            true
+        }
+    };
+    if !completed {
+        let message = format!("Task timed out after {}s", spec.timeout_secs.unwrap_or(0));
+        println!("⏰ {}", message);
+        if let Some(interrupted) = run.stages.last_mut().filter(|stage| stage.error.is_some()) {
+            interrupted.error = Some(message);
+        }
+    }
+
+    // Otherwise the extraction could put the model back after cleanup wiped it
+    if let Some(extraction) = run.extraction.take() {
+        if !extraction.is_finished() {
+            println!("⏳ Waiting for the interrupted extraction to stop before cleanup");
+            let _ = extraction.await;
+        }
+    }
+    if let Some(client) = run.client.take() {
+        stage(&mut run.stages, "unload", client.unload_model()).await;
+        if !spec.keep_model {
+            stage(&mut run.stages, "cleanup", async {
+                Ok(client.wipe_model_dir().map(|_| ())?)
+            })
+            .await;
+        }
+    } else if !spec.keep_model {
+        let model_dir = spec.repository.join(&spec.model_name);
+        stage(&mut run.stages, "cleanup", async {
+            Ok(wipe_dir(&model_dir).map(|_| ())?)
+        })
+        .await;
+    }
+
+    let error = run.stages.iter().find_map(|stage| stage.error.clone());
+    let result = TaskResult {
+        task_id: spec.task_id.clone(),
+        model_name: spec.model_name.clone(),
+        success: error.is_none(),
+        started_at_ms,
+        duration_ms: started.elapsed().as_millis() as u64,
+        stages: run.stages,
+        inferences: run.inferences,
+        failed_inferences: run.failed_inferences,
+        error,
+    };
+    if result.success {
+        println!(
+            "✅ Task '{}' finished in {} ms",
+            spec.task_id, result.duration_ms
+        );
+    } else {
+        println!("❌ Task '{}' failed: {:?}", spec.task_id, result.error);
+    }
+    result
+}
+
+/// State carried from stage to stage
+#[derive(Default)]
+struct TaskRun {
+    stages: Vec<StageResult>,
+    client: Option<TritonClient>,
+    /// Kept so a timed-out task can wait for the blocking extraction, which cannot be aborted
+    extraction: Option<JoinHandle<io::Result<ExtractionStatus>>>,
+    inferences: usize,
+    failed_inferences: usize,
+}
+
+/// Times `work` and records its outcome in `stages`; `None` means the stage failed
+async fn stage<T>(
+    stages: &mut Vec<StageResult>,
+    name: &str,
+    work: impl Future<Output = Result<T, TaskError>>,
+) -> Option<T> {
+    println!("▶️ Stage '{}'", name);
+    // Recorded up front, so a stage cut short by the timeout still shows up
+    stages.push(StageResult {
+        stage: name.to_string(),
+        duration_ms: 0,
+        error: Some("interrupted".to_string()),
+    });
+    let started = Instant::now();
+    let outcome = work.await;
+    let record = stages.last_mut().expect("pushed above");
+    record.duration_ms = started.elapsed().as_millis() as u64;
+    match outcome {
+        Ok(value) => {
+            record.error = None;
+            Some(value)
+        }
+        Err(e) => {
+            println!("❌ Stage '{}' failed: {}", name, e);
+            record.error = Some(e.to_string());
+            None
+        }
+    }
+}
+
+impl TaskRun {
+    /// Every stage up to writing the outputs, stopping at the first failure
+    async fn main_stages(&mut self, spec: &TaskSpec, options: &ClientOptions) -> Option<()> {
+        let requests = stage(&mut self.stages, "read_inputs", async {
+            read_requests(&spec.inputs)
+        })
+        .await?;
+
+        stage(&mut self.stages, "fetch", async {
+            let source: Box<dyn ModelSource> = if is_url(&spec.archive) {
+                Box::new(HttpSource::new(&spec.archive))
+            } else {
+                Box::new(LocalSource::new(PathBuf::from(&spec.archive)))
+            };
+            ModelExtractor::from_source(
+                source.as_ref(),
+                &spec.model_name,
+                spec.repository.clone(),
+                &spec.expected_sha256,
+            )
+            .await?;
+            Ok(())
+        })
+        .await?;
+
+        let extractor = options.extractor(&spec.model_name, spec.repository.clone());
+        let extraction = self.extraction.insert(tokio::task::spawn_blocking(move || {
+            extractor.ensure_extracted()
+        }));
+        stage(&mut self.stages, "extract", async {
+            Ok(extraction.await??)
+        })
+        .await?;
+
+        let client = TritonClient::connect(
+            &spec.triton_url,
+            &spec.model_name,
+            spec.repository.clone(),
+            options,
+        );
+        stage(&mut self.stages, "verify", async {
+            Ok(client.verify_manifest()?)
+        })
+        .await?;
+        // Kept before loading, so a load cut off by the task timeout is still unloaded
+        let client = self.client.insert(client);
+        stage(&mut self.stages, "load", client.load_model()).await?;
+
+        let mut lines = Vec::with_capacity(requests.len());
+        let mut failed = 0;
+        let infer = stage(&mut self.stages, "infer", async {
+            for inputs in requests {
+                match client.infer_with_receipt(inputs).await {
+                    Ok((outputs, _)) => lines.push(outputs),
+                    Err(e) => {
+                        failed += 1;
+                        lines.push(json!({ "error": e.to_string() }));
+                    }
+                }
+            }
+            match failed {
+                0 => Ok(()),
+                _ => Err(format!("{} of {} inferences failed", failed, lines.len()).into()),
+            }
+        })
+        .await;
+        self.inferences = lines.len();
+        self.failed_inferences = failed;
+
+        // Partial results are still worth keeping
+        stage(&mut self.stages, "write_outputs", async {
+            write_responses(&spec.outputs, &lines)
+        })
+        .await?;
+        infer
+    }
+}
+
+fn read_requests(path: &Path) -> Result<Vec<HashMap<String, TensorData>>, TaskError> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("{:?} line {}: {}", path, number + 1, e).into())
+        })
+        .collect()
+}
+
+/// Whether a task's `archive` is fetched over HTTP rather than read from disk
+fn is_url(archive: &str) -> bool {
+    archive.starts_with("http://") || archive.starts_with("https://")
+}
+
+fn write_responses(path: &Path, responses: &[Value]) -> Result<(), TaskError> {
+    let mut contents = String::new();
+    for response in responses {
+        contents.push_str(&response.to_string());
+        contents.push('\n');
+    }
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{hash_bytes, HashAlgorithm};
+    use crate::lock::ModelLock;
+    use crate::test_support::{
+        serve, simple_inputs, simple_session, tar_gz, write_cassette, TestResponse,
+    };
+    use crate::transport::Transport;
+
+    fn simple_archive() -> Vec<u8> {
+        tar_gz(&[
+            ("simple/config.pbtxt", b"name: \"simple\"\n"),
+            ("simple/1/model.onnx", b"onnx"),
+        ])
+    }
+
+    fn write_task(dir: &Path, expected_sha256: &str) -> PathBuf {
+        let archive = dir.join("simple.tar.gz");
+        std::fs::write(&archive, simple_archive()).unwrap();
+        std::fs::write(
+            dir.join("inputs.jsonl"),
+            format!("{}\n", serde_json::to_string(&simple_inputs()).unwrap()),
+        )
+        .unwrap();
+        let task = json!({
+            "task_id": "job-1",
+            "triton_url": "http://triton.invalid/v2",
+            "model_name": "simple",
+            "archive": "simple.tar.gz",
+            "expected_sha256": expected_sha256,
+            "repository": "repo",
+            "inputs": "inputs.jsonl",
+            "outputs": "outputs.jsonl",
+            "timeout_secs": 30
+        });
+        let path = dir.join("task.json");
+        std::fs::write(&path, task.to_string()).unwrap();
+        path
+    }
+
+    fn stage_names(result: &TaskResult) -> Vec<&str> {
+        result.stages.iter().map(|s| s.stage.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn runs_every_stage() {
+        let dir = tempfile::tempdir().unwrap();
+        let digest = hash_bytes(&simple_archive(), HashAlgorithm::Sha256);
+        let spec = TaskSpec::load(&write_task(dir.path(), &digest)).unwrap();
+        assert_eq!(spec.outputs, dir.path().join("outputs.jsonl"));
+        assert_eq!(spec.repository, dir.path().join("repo"));
+        assert_eq!(
+            spec.archive,
+            dir.path().join("simple.tar.gz").to_string_lossy()
+        );
+        assert_eq!(spec.result, Some(dir.path().join("task.result.json")));
+
+        let cassette = dir.path().join("simple.cassette");
+        write_cassette(&cassette, &simple_session());
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            ..ClientOptions::default()
+        };
+        let result = run_task(&spec, options).await;
+
+        assert!(result.success, "{:?}", result);
+        assert_eq!(
+            stage_names(&result),
+            [
+                "read_inputs",
+                "fetch",
+                "extract",
+                "verify",
+                "load",
+                "infer",
+                "write_outputs",
+                "unload",
+                "cleanup"
+            ]
+        );
+        assert_eq!((result.inferences, result.failed_inferences), (1, 0));
+        let outputs = std::fs::read_to_string(&spec.outputs).unwrap();
+        let response: Value = serde_json::from_str(outputs.trim()).unwrap();
+        assert_eq!(response["outputs"][0]["data"], json!([2, 3, 4, 5]));
+        assert!(!dir.path().join("repo/simple").exists());
+    }
+
+    #[tokio::test]
+    async fn failed_stage_stops_task_and_still_cleans_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = TaskSpec::load(&write_task(dir.path(), &"0".repeat(64))).unwrap();
+        let cassette = dir.path().join("empty.cassette");
+        std::fs::write(&cassette, "").unwrap();
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            ..ClientOptions::default()
+        };
+        let result = run_task(&spec, options).await;
+
+        assert!(!result.success);
+        assert_eq!(stage_names(&result), ["read_inputs", "fetch", "cleanup"]);
+        assert!(result.error.unwrap().contains("Checksum"));
+        assert!(result.stages[2].error.is_none());
+        assert!(!spec.outputs.exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn timeout_during_load_still_unloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let digest = hash_bytes(&simple_archive(), HashAlgorithm::Sha256);
+        let mut spec = TaskSpec::load(&write_task(dir.path(), &digest)).unwrap();
+        let (url, seen) = serve(|request| {
+            if request.path.ends_with("/load") {
+                std::thread::sleep(Duration::from_secs(2));
+            }
+            TestResponse::new(200, b"{}".to_vec())
+        })
+        .await;
+        spec.triton_url = format!("{}/v2", url);
+        spec.timeout_secs = Some(1);
+
+        let result = run_task(&spec, ClientOptions::default()).await;
+
+        assert!(!result.success);
+        assert_eq!(
+            stage_names(&result),
+            [
+                "read_inputs",
+                "fetch",
+                "extract",
+                "verify",
+                "load",
+                "unload",
+                "cleanup"
+            ]
+        );
+        assert!(result.stages[4]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("timed out"));
+        assert!(seen
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|request| request.path == "/v2/repository/models/simple/unload"));
+    }
+
+    #[tokio::test]
+    async fn bad_model_name_fails_without_touching_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let digest = hash_bytes(&simple_archive(), HashAlgorithm::Sha256);
+        let path = write_task(dir.path(), &digest);
+        let valid = TaskSpec::load(&path).unwrap();
+        let sentinel = dir.path().join("repo/other/keep.txt");
+        std::fs::create_dir_all(sentinel.parent().unwrap()).unwrap();
+        std::fs::write(&sentinel, b"keep").unwrap();
+
+        for model_name in ["", ".", "..", "../other", "a/b"] {
+            let mut task: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+            task["model_name"] = json!(model_name);
+            std::fs::write(&path, task.to_string()).unwrap();
+            let err = TaskSpec::load(&path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", model_name);
+
+            // A spec built without `load` is refused before any stage runs
+            let spec = TaskSpec {
+                model_name: model_name.to_string(),
+                ..valid.clone()
+            };
+            let result = run_task(&spec, ClientOptions::default()).await;
+            assert!(!result.success);
+            assert!(result.stages.is_empty());
+        }
+        assert_eq!(std::fs::read(&sentinel).unwrap(), b"keep");
+        assert!(dir.path().join("simple.tar.gz").exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn timeout_during_extraction_waits_before_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        let digest = hash_bytes(&simple_archive(), HashAlgorithm::Sha256);
+        let mut spec = TaskSpec::load(&write_task(dir.path(), &digest)).unwrap();
+        spec.timeout_secs = Some(1);
+
+        // Another process holds the model past the deadline, then lets extraction go ahead
+        let held = ModelLock::acquire(&spec.repository, "simple", Duration::ZERO).unwrap();
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(1500));
+            drop(held);
+        });
+        let result = run_task(&spec, ClientOptions::default()).await;
+        holder.join().unwrap();
+        // Give an extraction still running in the background time to finish
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert!(!result.success);
+        assert_eq!(
+            stage_names(&result),
+            ["read_inputs", "fetch", "extract", "cleanup"]
+        );
+        assert!(result.stages[2]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("timed out"));
+        // The extraction finished before cleanup, so nothing was put back afterwards
+        assert!(!spec.repository.join("simple").exists());
+    }
+}
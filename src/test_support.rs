@@ -0,0 +1,216 @@
+//! Minimal HTTP/1.1 server for tests that need something to download from,
+//! a recorded Triton session for tests that drive `TritonClient` via replay,
+//! and an in-memory archive builder for tests that need a model to extract.
+
+use crate::client::TensorData;
+use crate::transport::Exchange;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[derive(Clone, Debug)]
+pub(crate) struct TestRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased
+    pub headers: HashMap<String, String>,
+}
+
+pub(crate) struct TestResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    pub fn new(status: u16, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body,
+        }
+    }
+}
+
+/// Starts a server on a random local port and returns its base URL and the requests it has seen
+pub(crate) async fn serve<F>(handler: F) -> (String, Arc<Mutex<Vec<TestRequest>>>)
+where
+    F: Fn(&TestRequest) -> TestResponse + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let handler = Arc::new(handler);
+
+    let log = seen.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(_) => return,
+            };
+            let handler = handler.clone();
+            let log = log.clone();
+            tokio::spawn(async move {
+                let mut raw = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !raw.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => raw.extend_from_slice(&buf[..n]),
+                    }
+                }
+
+                let text = String::from_utf8_lossy(&raw).to_string();
+                let mut lines = text.split("\r\n");
+                let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+                let request = TestRequest {
+                    method: request_line.next().unwrap_or_default().to_string(),
+                    path: request_line.next().unwrap_or_default().to_string(),
+                    headers: lines
+                        .take_while(|line| !line.is_empty())
+                        .filter_map(|line| line.split_once(':'))
+                        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+                        .collect(),
+                };
+                log.lock().unwrap().push(request.clone());
+
+                let response = handler(&request);
+                let mut head = format!(
+                    "HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    response.status,
+                    response.body.len()
+                );
+                for (name, value) in &response.headers {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                head.push_str("\r\n");
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(&response.body).await;
+                let _ = socket.shutdown().await;
+            });
+        }
+    });
+
+    (format!("http://{}", address), seen)
+}
+
+/// Serves `body` honouring `Range: bytes=<start>-` requests
+pub(crate) fn ranged(request: &TestRequest, body: &[u8]) -> TestResponse {
+    let start = request
+        .headers
+        .get("range")
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.strip_suffix('-'))
+        .and_then(|start| start.parse::<usize>().ok());
+
+    match start {
+        Some(start) if start >= body.len() => TestResponse::new(416, Vec::new()),
+        Some(start) => {
+            let mut response = TestResponse::new(206, body[start..].to_vec());
+            response.headers.push((
+                "Content-Range".to_string(),
+                format!("bytes {}-{}/{}", start, body.len() - 1, body.len()),
+            ));
+            response
+        }
+        None => TestResponse::new(200, body.to_vec()),
+    }
+}
+
+pub(crate) fn exchange(
+    method: &str,
+    path: &str,
+    request_body: Option<Value>,
+    response: Value,
+) -> Exchange {
+    Exchange {
+        method: method.to_string(),
+        path: path.to_string(),
+        request_body,
+        status: 200,
+        response_body: response.to_string(),
+    }
+}
+
+/// Writes `exchanges` as a replayable cassette
+pub(crate) fn write_cassette(path: &Path, exchanges: &[Exchange]) {
+    let mut file = std::fs::File::create(path).unwrap();
+    for exchange in exchanges {
+        writeln!(file, "{}", serde_json::to_string(exchange).unwrap()).unwrap();
+    }
+}
+
+/// A full load, metadata, infer and unload session against the `simple` add model
+pub(crate) fn simple_session() -> Vec<Exchange> {
+    let metadata = json!({
+        "name": "simple",
+        "versions": ["1"],
+        "platform": "onnxruntime_onnx",
+        "inputs": [
+            { "name": "INPUT0", "datatype": "INT32", "shape": [1, 4] },
+            { "name": "INPUT1", "datatype": "INT32", "shape": [1, 4] }
+        ],
+        "outputs": [{ "name": "OUTPUT0", "datatype": "INT32", "shape": [1, 4] }]
+    });
+    let infer_request = json!({ "inputs": [
+        { "name": "INPUT0", "shape": [1, 4], "datatype": "INT32", "data": [1, 2, 3, 4] },
+        { "name": "INPUT1", "shape": [1, 4], "datatype": "INT32", "data": [1, 1, 1, 1] }
+    ]});
+    let infer_response = json!({
+        "model_name": "simple",
+        "outputs": [{ "name": "OUTPUT0", "datatype": "INT32", "shape": [1, 4], "data": [2, 3, 4, 5] }]
+    });
+
+    vec![
+        exchange("GET", "/health/live", None, json!({})),
+        exchange("GET", "/health/ready", None, json!({})),
+        exchange(
+            "POST",
+            "/repository/models/simple/load",
+            Some(json!({})),
+            json!({}),
+        ),
+        exchange("GET", "/models/simple", None, metadata.clone()),
+        exchange("GET", "/models/simple", None, metadata),
+        exchange(
+            "POST",
+            "/models/simple/infer",
+            Some(infer_request),
+            infer_response,
+        ),
+        exchange(
+            "POST",
+            "/repository/models/simple/unload",
+            Some(json!({})),
+            json!({}),
+        ),
+    ]
+}
+
+/// Inputs matching the infer request recorded in `simple_session`
+pub(crate) fn simple_inputs() -> HashMap<String, TensorData> {
+    let mut inputs = HashMap::new();
+    inputs.insert("INPUT0".to_string(), TensorData::I32(vec![1, 2, 3, 4]));
+    inputs.insert("INPUT1".to_string(), TensorData::I32(vec![1, 1, 1, 1]));
+    inputs
+}
+
+/// Packs `files` (path, contents) into an in-memory tar.gz archive
+pub(crate) fn tar_gz(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, *data).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
@@ -0,0 +1,286 @@
+use reqwest::{Client, Method};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Limit on each HTTP request, unless `Transport::with_request_timeout` sets another
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A single HTTP exchange with the Triton server, as stored in a cassette file.
+///
+/// `path` is relative to the server URL (e.g. `/models/simple/infer`) so a
+/// cassette recorded against one host can be replayed against any other.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    pub path: String,
+    pub request_body: Option<Value>,
+    pub status: u16,
+    pub response_body: String,
+}
+
+/// Status and body of a response, detached from the underlying connection
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_str(&self.body)
+    }
+}
+
+/// How `TritonClient` talks to the server.
+///
+/// `Record` behaves like `Http` but appends every exchange to a cassette file
+/// (one JSON object per line). `Replay` never touches the network and serves
+/// the exchanges of a cassette back in the order they were recorded; a request
+/// that does not match the next exchange is rejected without consuming it.
+#[derive(Clone)]
+pub enum Transport {
+    Http(Client),
+    Record {
+        client: Client,
+        cassette: Arc<Mutex<File>>,
+    },
+    Replay(Arc<Mutex<VecDeque<Exchange>>>),
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Http(http_client(DEFAULT_REQUEST_TIMEOUT))
+    }
+}
+
+impl Transport {
+    /// Creates a recording transport writing to `cassette_path`, truncating any previous recording
+    pub fn record(cassette_path: &Path) -> io::Result<Self> {
+        let file = File::create(cassette_path)?;
+        Ok(Transport::Record {
+            client: http_client(DEFAULT_REQUEST_TIMEOUT),
+            cassette: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Creates a transport that serves the exchanges stored in `cassette_path`
+    pub fn replay(cassette_path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(cassette_path)?);
+        let mut exchanges = VecDeque::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange: Exchange = serde_json::from_str(&line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            exchanges.push_back(exchange);
+        }
+        Ok(Transport::Replay(Arc::new(Mutex::new(exchanges))))
+    }
+
+    /// Limits every HTTP request, connecting included, to `timeout`; replays are unaffected
+    pub fn with_request_timeout(self, timeout: Duration) -> Self {
+        match self {
+            Transport::Http(_) => Transport::Http(http_client(timeout)),
+            Transport::Record { cassette, .. } => Transport::Record {
+                client: http_client(timeout),
+                cassette,
+            },
+            replay @ Transport::Replay(_) => replay,
+        }
+    }
+
+    pub async fn get(
+        &self,
+        base_url: &str,
+        path: &str,
+    ) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
+        self.send(Method::GET, base_url, path, None).await
+    }
+
+    pub async fn post(
+        &self,
+        base_url: &str,
+        path: &str,
+        body: &Value,
+    ) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
+        self.send(Method::POST, base_url, path, Some(body)).await
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        base_url: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            Transport::Http(client) => Self::send_http(client, method, base_url, path, body).await,
+            Transport::Record { client, cassette } => {
+                let response =
+                    Self::send_http(client, method.clone(), base_url, path, body).await?;
+                let exchange = Exchange {
+                    method: method.to_string(),
+                    path: path.to_string(),
+                    request_body: body.cloned(),
+                    status: response.status,
+                    response_body: response.body.clone(),
+                };
+                let mut file = cassette.lock().map_err(|_| "cassette lock poisoned")?;
+                Self::write_exchange(&mut file, &exchange)?;
+                Ok(response)
+            }
+            Transport::Replay(exchanges) => {
+                let mut exchanges = exchanges.lock().map_err(|_| "cassette lock poisoned")?;
+                let expected = exchanges
+                    .front()
+                    .ok_or_else(|| format!("❌ Cassette exhausted at {} {}", method, path))?;
+
+                // A mismatched request leaves the exchange queued for the request it belongs to
+                if expected.method != method.as_str()
+                    || expected.path != path
+                    || expected.request_body.as_ref() != body
+                {
+                    return Err(format!(
+                        "❌ Cassette mismatch: expected {} {}, got {} {}",
+                        expected.method, expected.path, method, path
+                    )
+                    .into());
+                }
+
+                let exchange = exchanges.pop_front().expect("checked above");
+                Ok(HttpResponse {
+                    status: exchange.status,
+                    body: exchange.response_body,
+                })
+            }
+        }
+    }
+
+    async fn send_http(
+        client: &Client,
+        method: Method,
+        base_url: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
+        let timed_out = |e: reqwest::Error| -> Box<dyn std::error::Error + Send + Sync> {
+            if e.is_timeout() {
+                format!("❌ Request timed out: {} {}", method, path).into()
+            } else {
+                e.into()
+            }
+        };
+        let mut request = client.request(method.clone(), format!("{}{}", base_url, path));
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send().await.map_err(timed_out)?;
+        let status = response.status().as_u16();
+        let body = response.text().await.map_err(timed_out)?;
+        Ok(HttpResponse { status, body })
+    }
+
+    fn write_exchange(file: &mut File, exchange: &Exchange) -> io::Result<()> {
+        let line = serde_json::to_string(exchange)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writeln!(file, "{}", line)?;
+        file.flush()
+    }
+}
+
+fn http_client(timeout: Duration) -> Client {
+    Client::builder()
+        .connect_timeout(timeout.min(Duration::from_secs(30)))
+        .timeout(timeout)
+        .build()
+        .expect("HTTP client configuration is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ClientOptions, TritonClient};
+    use crate::test_support::{simple_inputs, simple_session, write_cassette};
+    use serde_json::json;
+    use tokio_util::sync::CancellationToken;
+
+    #[tokio::test]
+    async fn replays_recorded_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = dir.path().join("simple.cassette");
+        write_cassette(&cassette, &simple_session());
+
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            ..ClientOptions::default()
+        };
+        let client = TritonClient::with_options(
+            "http://triton.invalid/v2",
+            "simple",
+            dir.path().to_path_buf(),
+            options,
+        )
+        .await
+        .unwrap();
+
+        let result = client
+            .run_inference(simple_inputs(), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(result["outputs"][0]["data"], json!([2, 3, 4, 5]));
+    }
+
+    #[tokio::test]
+    async fn replay_rejects_unexpected_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = dir.path().join("simple.cassette");
+        write_cassette(&cassette, &simple_session()[..1]);
+
+        let transport = Transport::replay(&cassette).unwrap();
+        let err = transport
+            .get("http://triton.invalid/v2", "/health/ready")
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Cassette mismatch"));
+
+        // The mismatch did not use up the exchange recorded for this request
+        let response = transport
+            .get("http://triton.invalid/v2", "/health/live")
+            .await
+            .unwrap();
+        assert!(response.is_success());
+
+        let err = transport
+            .get("http://triton.invalid/v2", "/health/live")
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Cassette exhausted"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn request_timeout_cuts_off_hung_server() {
+        let (url, _) = crate::test_support::serve(|_| {
+            std::thread::sleep(Duration::from_secs(1));
+            crate::test_support::TestResponse::new(200, b"{}".to_vec())
+        })
+        .await;
+
+        let transport = Transport::default().with_request_timeout(Duration::from_millis(100));
+        let err = transport.get(&url, "/health/live").await.err().unwrap();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+    }
+}
@@ -0,0 +1,569 @@
+use crate::client::{ClientOptions, TritonClient};
+use crate::hash::{hash_file_async, HashAlgorithm};
+use crate::models::{ExtractionStatus, ModelExtractor, ARCHIVE_EXTENSIONS};
+use crate::source::LocalSource;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Step of provisioning a dropped archive, as reported in `WatchEvent::Failed`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchStage {
+    Verify,
+    Extract,
+    Validate,
+    Load,
+}
+
+/// Progress of the archives picked up by a `DropWatcher`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// A new archive appeared; it is processed once it stops changing
+    Detected {
+        path: PathBuf,
+    },
+    /// The archive matched its `.sha256` file and was moved into the repository
+    Verified {
+        model_name: String,
+        sha256: String,
+    },
+    /// The archive came without a `.sha256` file and was moved into the
+    /// repository anyway, since unverified archives are allowed
+    Unverified {
+        model_name: String,
+        sha256: String,
+    },
+    Extracted {
+        model_name: String,
+        status: ExtractionStatus,
+    },
+    /// The model directory has a config.pbtxt and a version directory, and
+    /// matches its manifest if it has one
+    Validated {
+        model_name: String,
+    },
+    Loaded {
+        model_name: String,
+    },
+    /// Provisioning stopped; the archive is retried only once it changes
+    Failed {
+        path: PathBuf,
+        stage: WatchStage,
+        error: String,
+    },
+}
+
+/// Size and mtime of a dropped file, to tell when it has stopped being written
+type FileMetadata = (u64, Option<SystemTime>);
+
+/// State of a dropped archive and of its `.sha256` file, if there is one
+type FileState = (FileMetadata, Option<FileMetadata>);
+
+/// Watches a drop directory and provisions every model archive that lands in it.
+///
+/// The directory is polled rather than watched through OS notifications, so it
+/// also works on network filesystems. An archive is only picked up once its
+/// size and mtime, and those of its `<archive>.sha256` file, have held still
+/// for the settle time. The archive must match the digest in that file; an
+/// archive without one is refused unless unverified archives are allowed. The
+/// archive is then moved into the repository, extracted, checked and loaded
+/// into the server.
+pub struct DropWatcher {
+    drop_dir: PathBuf,
+    repository: PathBuf,
+    triton_url: String,
+    options: ClientOptions,
+    poll_interval: Duration,
+    settle_time: Duration,
+    allow_unverified: bool,
+    /// Files seen changing, with their state and when it was first seen
+    pending: HashMap<PathBuf, (FileState, Instant)>,
+    /// Files that failed, skipped until they change
+    failed: HashMap<PathBuf, FileState>,
+}
+
+impl DropWatcher {
+    pub fn new(drop_dir: PathBuf, repository: PathBuf, triton_url: &str) -> Self {
+        Self {
+            drop_dir,
+            repository,
+            triton_url: triton_url.to_string(),
+            options: ClientOptions::default(),
+            poll_interval: Duration::from_secs(2),
+            settle_time: Duration::from_secs(5),
+            allow_unverified: false,
+            pending: HashMap::new(),
+            failed: HashMap::new(),
+        }
+    }
+
+    /// Options for the extraction and the client that loads each model
+    pub fn with_client_options(mut self, options: ClientOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// How long an archive must stay unchanged before it is picked up
+    pub fn with_settle_time(mut self, settle_time: Duration) -> Self {
+        self.settle_time = settle_time;
+        self
+    }
+
+    /// Accepts archives dropped without a `.sha256` file, reporting them as `Unverified`
+    pub fn with_allow_unverified(mut self, allow_unverified: bool) -> Self {
+        self.allow_unverified = allow_unverified;
+        self
+    }
+
+    /// Runs the watcher on its own task and returns its events
+    pub fn spawn(self) -> (JoinHandle<io::Result<()>>, mpsc::Receiver<WatchEvent>) {
+        let (sender, receiver) = mpsc::channel(64);
+        (tokio::spawn(self.run(sender)), receiver)
+    }
+
+    /// Polls the drop directory until `events` is closed
+    pub async fn run(mut self, events: mpsc::Sender<WatchEvent>) -> io::Result<()> {
+        println!("👀 Watching {:?} for model archives", self.drop_dir);
+        while !events.is_closed() {
+            // A drop directory that is briefly missing or unreadable is retried on the next poll
+            if let Err(e) = self.poll(&events).await {
+                println!("⚠️ Failed to scan {:?}: {}", self.drop_dir, e);
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+        Ok(())
+    }
+
+    async fn poll(&mut self, events: &mpsc::Sender<WatchEvent>) -> io::Result<()> {
+        let mut settled = Vec::new();
+        let mut present = HashSet::new();
+        let mut entries = tokio::fs::read_dir(&self.drop_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if !entry.file_type().await?.is_file() || model_name_of(&path).is_none() {
+                continue;
+            }
+            present.insert(path.clone());
+            let metadata = entry.metadata().await?;
+            let sidecar = tokio::fs::metadata(sidecar_path(&path))
+                .await
+                .ok()
+                .map(|sidecar| (sidecar.len(), sidecar.modified().ok()));
+            let state = ((metadata.len(), metadata.modified().ok()), sidecar);
+            if self.failed.get(&path) == Some(&state) {
+                continue;
+            }
+
+            match self.pending.get(&path) {
+                Some((seen, since)) if *seen == state => {
+                    if since.elapsed() >= self.settle_time {
+                        settled.push(path);
+                    }
+                }
+                previous => {
+                    if previous.is_none() {
+                        let _ = events
+                            .send(WatchEvent::Detected { path: path.clone() })
+                            .await;
+                    }
+                    self.pending.insert(path, (state, Instant::now()));
+                }
+            }
+        }
+        // Forget files that were removed, so one dropped again under the same name is new
+        self.pending.retain(|path, _| present.contains(path));
+        self.failed.retain(|path, _| present.contains(path));
+
+        for path in settled {
+            let (state, _) = self
+                .pending
+                .remove(&path)
+                .expect("settled files are pending");
+            if let Err((stage, error)) = self.provision(&path, events).await {
+                println!(
+                    "❌ Provisioning {:?} failed at {:?}: {}",
+                    path, stage, error
+                );
+                self.failed.insert(path.clone(), state);
+                let _ = events.send(WatchEvent::Failed { path, stage, error }).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn provision(
+        &self,
+        path: &Path,
+        events: &mpsc::Sender<WatchEvent>,
+    ) -> Result<(), (WatchStage, String)> {
+        let model_name = model_name_of(path).expect("only archives are provisioned");
+        let (sha256, verified) = self
+            .verify_into_repository(path, &model_name)
+            .await
+            .map_err(|e| (WatchStage::Verify, e.to_string()))?;
+        let event = if verified {
+            WatchEvent::Verified {
+                model_name: model_name.clone(),
+                sha256,
+            }
+        } else {
+            WatchEvent::Unverified {
+                model_name: model_name.clone(),
+                sha256,
+            }
+        };
+        let _ = events.send(event).await;
+
+        let extractor = self.options.extractor(&model_name, self.repository.clone());
+        let status = tokio::task::spawn_blocking(move || extractor.ensure_extracted())
+            .await
+            .map_err(|e| (WatchStage::Extract, e.to_string()))?
+            .map_err(|e| (WatchStage::Extract, e.to_string()))?;
+        let _ = events
+            .send(WatchEvent::Extracted {
+                model_name: model_name.clone(),
+                status,
+            })
+            .await;
+
+        let client = TritonClient::connect(
+            &self.triton_url,
+            &model_name,
+            self.repository.clone(),
+            &self.options,
+        );
+        // Both walk the model directory, and the manifest check hashes every file
+        let model_dir = self.repository.join(&model_name);
+        let mut client = tokio::task::spawn_blocking(move || {
+            validate_layout(&model_dir)?;
+            client.verify_manifest()?;
+            Ok::<_, io::Error>(client)
+        })
+        .await
+        .map_err(|e| (WatchStage::Validate, e.to_string()))?
+        .map_err(|e| (WatchStage::Validate, e.to_string()))?;
+        let _ = events
+            .send(WatchEvent::Validated {
+                model_name: model_name.clone(),
+            })
+            .await;
+
+        // Fails when the server refuses the model, not only when it cannot be reached
+        client
+            .load_model()
+            .await
+            .map_err(|e| (WatchStage::Load, e.to_string()))?;
+        let _ = events.send(WatchEvent::Loaded { model_name }).await;
+        Ok(())
+    }
+
+    /// Moves the archive into the repository, checked against its `.sha256` file.
+    ///
+    /// Returns the archive's digest and whether it was checked against one.
+    async fn verify_into_repository(
+        &self,
+        path: &Path,
+        model_name: &str,
+    ) -> io::Result<(String, bool)> {
+        let sidecar = sidecar_path(path);
+        let (expected, verified) = match tokio::fs::read_to_string(&sidecar).await {
+            Ok(contents) => (
+                contents
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                true,
+            ),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && self.allow_unverified => {
+                println!("⚠️ No {:?}, accepting {:?} unverified", sidecar, path);
+                let digest =
+                    hash_file_async(path.to_path_buf(), HashAlgorithm::Sha256, None).await?;
+                (digest, false)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("No {:?} to verify the archive against", sidecar),
+                ))
+            }
+            Err(e) => return Err(e),
+        };
+
+        ModelExtractor::from_source(
+            &LocalSource::new(path.to_path_buf()),
+            model_name,
+            self.repository.clone(),
+            &expected,
+        )
+        .await?;
+        tokio::fs::remove_file(path).await?;
+        let _ = tokio::fs::remove_file(&sidecar).await;
+        Ok((expected.to_lowercase(), verified))
+    }
+}
+
+/// Where the expected SHA-256 of a dropped archive is read from
+fn sidecar_path(archive: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.sha256", archive.display()))
+}
+
+/// Model name of a dropped archive: its file name without the archive suffix.
+///
+/// Hidden files are skipped, since they are usually still being downloaded.
+fn model_name_of(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    if file_name.starts_with('.') {
+        return None;
+    }
+    ARCHIVE_EXTENSIONS.iter().find_map(|ext| {
+        file_name
+            .strip_suffix(ext)?
+            .strip_suffix('.')
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+    })
+}
+
+/// Checks that `model_dir` has a config.pbtxt and a non-empty numeric version directory
+fn validate_layout(model_dir: &Path) -> io::Result<()> {
+    let invalid = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid model layout in {:?}: {}", model_dir, reason),
+        )
+    };
+    if !model_dir.join("config.pbtxt").is_file() {
+        return Err(invalid("config.pbtxt is missing"));
+    }
+    for entry in std::fs::read_dir(model_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_dir()
+            && name.chars().all(|c| c.is_ascii_digit())
+            && std::fs::read_dir(entry.path())?.next().is_some()
+        {
+            return Ok(());
+        }
+    }
+    Err(invalid("no version directory with model files"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{simple_session, tar_gz, write_cassette};
+    use crate::transport::Transport;
+
+    async fn next_event(events: &mut mpsc::Receiver<WatchEvent>) -> WatchEvent {
+        tokio::time::timeout(Duration::from_secs(10), events.recv())
+            .await
+            .expect("watcher went quiet")
+            .expect("watcher stopped")
+    }
+
+    #[test]
+    fn names_models_after_archives() {
+        assert_eq!(
+            model_name_of(Path::new("drop/resnet.tar.gz")).as_deref(),
+            Some("resnet")
+        );
+        assert_eq!(
+            model_name_of(Path::new("drop/a.b.zip")).as_deref(),
+            Some("a.b")
+        );
+        assert_eq!(model_name_of(Path::new("drop/.resnet.tar.gz")), None);
+        assert_eq!(model_name_of(Path::new("drop/resnet.tar.gz.sha256")), None);
+        assert_eq!(model_name_of(Path::new("drop/.tar.gz")), None);
+    }
+
+    #[tokio::test]
+    async fn provisions_dropped_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let drop_dir = dir.path().join("drop");
+        let repository = dir.path().join("repo");
+        std::fs::create_dir_all(&drop_dir).unwrap();
+
+        let cassette = dir.path().join("simple.cassette");
+        write_cassette(&cassette, &simple_session()[..3]);
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            ..ClientOptions::default()
+        };
+        let (watcher, mut events) = DropWatcher::new(
+            drop_dir.clone(),
+            repository.clone(),
+            "http://triton.invalid/v2",
+        )
+        .with_client_options(options)
+        .with_poll_interval(Duration::from_millis(20))
+        .with_settle_time(Duration::from_millis(100))
+        .spawn();
+
+        // A digest that does not match fails verification and leaves the file alone
+        let broken = drop_dir.join("broken.tar.gz");
+        std::fs::write(&broken, tar_gz(&[("broken/config.pbtxt", b"name")])).unwrap();
+        std::fs::write(drop_dir.join("broken.tar.gz.sha256"), "0".repeat(64)).unwrap();
+        assert_eq!(
+            next_event(&mut events).await,
+            WatchEvent::Detected {
+                path: broken.clone()
+            }
+        );
+        match next_event(&mut events).await {
+            WatchEvent::Failed { path, stage, .. } => {
+                assert_eq!((path, stage), (broken.clone(), WatchStage::Verify))
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(broken.exists());
+
+        // Without a digest to check against, the archive is refused
+        let unsigned = drop_dir.join("unsigned.tar.gz");
+        std::fs::write(&unsigned, tar_gz(&[("unsigned/config.pbtxt", b"name")])).unwrap();
+        assert_eq!(
+            next_event(&mut events).await,
+            WatchEvent::Detected {
+                path: unsigned.clone()
+            }
+        );
+        match next_event(&mut events).await {
+            WatchEvent::Failed { path, stage, .. } => {
+                assert_eq!((path, stage), (unsigned.clone(), WatchStage::Verify))
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(unsigned.exists());
+
+        let archive = tar_gz(&[
+            ("simple/config.pbtxt", b"name: \"simple\"\n"),
+            ("simple/1/model.onnx", b"onnx"),
+        ]);
+        let dropped = drop_dir.join("simple.tar.gz");
+        std::fs::write(&dropped, &archive[..10]).unwrap();
+        assert_eq!(
+            next_event(&mut events).await,
+            WatchEvent::Detected {
+                path: dropped.clone()
+            }
+        );
+        // Still being written: the settle timer starts over, and again for the digest file
+        std::fs::write(&dropped, &archive).unwrap();
+        std::fs::write(
+            drop_dir.join("simple.tar.gz.sha256"),
+            format!(
+                "{}  simple.tar.gz\n",
+                crate::hash::hash_bytes(&archive, HashAlgorithm::Sha256)
+            ),
+        )
+        .unwrap();
+
+        match next_event(&mut events).await {
+            WatchEvent::Verified { model_name, sha256 } => {
+                assert_eq!(model_name, "simple");
+                assert_eq!(
+                    sha256,
+                    crate::hash::hash_bytes(&archive, HashAlgorithm::Sha256)
+                );
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(
+            next_event(&mut events).await,
+            WatchEvent::Extracted {
+                model_name: "simple".to_string(),
+                status: ExtractionStatus::Extracted
+            }
+        );
+        assert_eq!(
+            next_event(&mut events).await,
+            WatchEvent::Validated {
+                model_name: "simple".to_string()
+            }
+        );
+        assert_eq!(
+            next_event(&mut events).await,
+            WatchEvent::Loaded {
+                model_name: "simple".to_string()
+            }
+        );
+        assert!(!dropped.exists());
+        assert_eq!(
+            std::fs::read(repository.join("simple/1/model.onnx")).unwrap(),
+            b"onnx"
+        );
+
+        drop(events);
+        watcher.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn survives_missing_drop_dir_and_reports_refused_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let drop_dir = dir.path().join("drop");
+        let repository = dir.path().join("repo");
+
+        let mut session = simple_session();
+        session.truncate(3);
+        session[2].status = 400;
+        let cassette = dir.path().join("refused.cassette");
+        write_cassette(&cassette, &session);
+        let options = ClientOptions {
+            transport: Transport::replay(&cassette).unwrap(),
+            ..ClientOptions::default()
+        };
+        // The drop directory does not exist yet, so the first polls fail
+        let (watcher, mut events) = DropWatcher::new(
+            drop_dir.clone(),
+            repository.clone(),
+            "http://triton.invalid/v2",
+        )
+        .with_client_options(options)
+        .with_poll_interval(Duration::from_millis(20))
+        .with_settle_time(Duration::from_millis(50))
+        .with_allow_unverified(true)
+        .spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!watcher.is_finished());
+
+        std::fs::create_dir_all(&drop_dir).unwrap();
+        let dropped = drop_dir.join("simple.tar.gz");
+        std::fs::write(
+            &dropped,
+            tar_gz(&[
+                ("simple/config.pbtxt", b"name: \"simple\"\n"),
+                ("simple/1/model.onnx", b"onnx"),
+            ]),
+        )
+        .unwrap();
+
+        let mut unverified = false;
+        loop {
+            match next_event(&mut events).await {
+                WatchEvent::Failed { path, stage, error } => {
+                    assert_eq!((path, stage), (dropped.clone(), WatchStage::Load));
+                    assert!(error.contains("400"), "{}", error);
+                    break;
+                }
+                WatchEvent::Loaded { .. } => panic!("refused model reported as loaded"),
+                WatchEvent::Unverified { .. } => unverified = true,
+                WatchEvent::Verified { .. } => {
+                    panic!("archive without a digest reported as verified")
+                }
+                _ => {}
+            }
+        }
+        assert!(unverified);
+
+        drop(events);
+        watcher.await.unwrap().unwrap();
+    }
+}